@@ -0,0 +1,518 @@
+use crate::command::{GCodeCommand, GCodeDistanceMode, GCodeParams};
+use crate::handler::{GCodeFlow, GCodeValueHandler};
+use crate::modal::{GCodeModalState, GCodeMotionMode};
+use crate::parser::{GCodeComment, GCodeLine, GCodeParser, GCodeValue};
+use crate::writer::GCodeWriter;
+use std::f64::consts::{PI, TAU};
+
+///
+/// @author <a href="mailto: angcyo@126.com">angcyo</a>
+/// @date 2026/10/18
+///
+/// 圆弧拟合, 将折线中连续在同一个圆弧上的点替换成`G2`/`G3`
+/// - 拟合的圆弧经过起点和终点, 中间的点以及每一段直线的中点到圆弧的距离都不超过[tolerance]
+#[derive(Clone, Debug)]
+pub struct GCodeArcFitter {
+    /// 误差, 单位和坐标相同
+    pub tolerance: f64,
+    /// 一个圆弧最少替换多少段直线
+    pub min_segments: usize,
+    /// 圆弧的最大半径, 更大的圆弧接近直线, 不拟合
+    pub max_radius: f64,
+    /// 一个圆弧最多替换多少段直线, 限制每一次检查的点数
+    pub max_segments: usize,
+}
+
+impl Default for GCodeArcFitter {
+    fn default() -> Self {
+        GCodeArcFitter {
+            tolerance: 0.01,
+            min_segments: 3,
+            max_radius: 1000.0,
+            max_segments: 200,
+        }
+    }
+}
+
+/// 拟合的结果, 按照顺序连接折线中的点
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GCodeFitSegment {
+    /// 直线连接到指定下标的点
+    Line(usize),
+    /// 圆弧连接到指定下标的点
+    Arc {
+        end: usize,
+        /// 圆心
+        cx: f64,
+        cy: f64,
+        /// 是否顺时针
+        clockwise: bool,
+    },
+}
+
+/// 数值计算的误差, 比如判断三点共线
+const FIT_EPSILON: f64 = 1e-9;
+
+/// [GCodeValueHandlerArcFit]最多收集多少个点, 超过之后先拟合输出, 限制内存占用
+const FIT_BUFFER_POINTS: usize = 1000;
+
+impl GCodeArcFitter {
+    /// 拟合折线
+    /// - [points] 折线的点, 第一个点为起点
+    /// - 一个圆弧最多覆盖[max_segments]段直线, 更长的圆弧分成多个
+    pub fn fit(&self, points: &[(f64, f64)]) -> Vec<GCodeFitSegment> {
+        let mut segments = vec![];
+        let mut start = 0;
+        let min_segments = self.min_segments.max(2);
+        let max_segments = self.max_segments.max(min_segments);
+        while start + 1 < points.len() {
+            let mut best = None;
+            let mut end = start + min_segments;
+            let last = points.len().min(start + max_segments + 1);
+            while end < last {
+                match self.fit_arc(&points[start..=end]) {
+                    Some(arc) => best = Some((end, arc)),
+                    None => break,
+                }
+                end += 1;
+            }
+            match best {
+                Some((end, (cx, cy, clockwise))) => {
+                    segments.push(GCodeFitSegment::Arc {
+                        end,
+                        cx,
+                        cy,
+                        clockwise,
+                    });
+                    start = end;
+                }
+                None => {
+                    segments.push(GCodeFitSegment::Line(start + 1));
+                    start += 1;
+                }
+            }
+        }
+        segments
+    }
+
+    /// 使用起点/中间点/终点确定圆, 检查所有的点是否在圆弧上
+    /// - 返回圆心和是否顺时针
+    fn fit_arc(&self, points: &[(f64, f64)]) -> Option<(f64, f64, bool)> {
+        let a = points[0];
+        let b = points[points.len() / 2];
+        let c = points[points.len() - 1];
+        let (cx, cy) = circle_center(a, b, c)?;
+        let radius = (a.0 - cx).hypot(a.1 - cy);
+        if radius > self.max_radius {
+            return None;
+        }
+        let clockwise = (b.0 - a.0) * (c.1 - b.1) - (b.1 - a.1) * (c.0 - b.0) < 0.0;
+
+        let mut sweep = 0.0;
+        let mut last_angle = (a.1 - cy).atan2(a.0 - cx);
+        for pair in points.windows(2) {
+            let (x, y) = pair[1];
+            if ((x - cx).hypot(y - cy) - radius).abs() > self.tolerance {
+                return None;
+            }
+            //直线的中点到圆弧的距离
+            let (mx, my) = ((pair[0].0 + x) / 2.0, (pair[0].1 + y) / 2.0);
+            if radius - (mx - cx).hypot(my - cy) > self.tolerance {
+                return None;
+            }
+            //每一段都沿着相同的方向转动
+            let angle = (y - cy).atan2(x - cx);
+            let mut delta = angle - last_angle;
+            if delta > PI {
+                delta -= TAU;
+            } else if delta <= -PI {
+                delta += TAU;
+            }
+            if (clockwise && delta >= 0.0) || (!clockwise && delta <= 0.0) {
+                return None;
+            }
+            sweep += delta;
+            last_angle = angle;
+        }
+        if f64::abs(sweep) >= TAU - FIT_EPSILON {
+            return None;
+        }
+        Some((cx, cy, clockwise))
+    }
+}
+
+/// 经过三个点的圆的圆心, 三点共线时返回[None]
+fn circle_center(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> Option<(f64, f64)> {
+    //以a为原点计算, 减少误差
+    let (bx, by) = (b.0 - a.0, b.1 - a.1);
+    let (cx, cy) = (c.0 - a.0, c.1 - a.1);
+    let d = 2.0 * (bx * cy - by * cx);
+    if d.abs() < FIT_EPSILON {
+        return None;
+    }
+    let b2 = bx * bx + by * by;
+    let c2 = cx * cx + cy * cy;
+    Some((a.0 + (cy * b2 - by * c2) / d, a.1 + (bx * c2 - cx * b2) / d))
+}
+
+/// 对GCode进行圆弧拟合, 连续的`G1`直线会替换成`G2`/`G3`圆弧
+/// - 只有绝对坐标下只包含`X`/`Y`的`G1`行会参与拟合, 其他的行原样输出
+/// - 带有注释/行号/`F`/`Z`/`E`等参数的行不会参与拟合, `/`开头的跳段行也不会
+/// - 最多收集[FIT_BUFFER_POINTS]个点就拟合输出, 很长的折线也不会全部保存在内存中
+/// - 结果在[writer]中
+#[derive(Default)]
+pub struct GCodeValueHandlerArcFit {
+    /// 圆弧拟合
+    pub fitter: GCodeArcFitter,
+    /// 模态状态, 在行与行之间保持
+    pub modal: GCodeModalState,
+    /// 输出
+    pub writer: GCodeWriter,
+    //--
+    /// 正在收集的折线, 工件坐标, 原始数值
+    points: Vec<(f64, f64)>,
+    /// 折线中每一个点对应的原始行
+    texts: Vec<String>,
+    /// 还没有输出的注释
+    comments: Vec<GCodeComment>,
+    /// 输出了圆弧之后, 输出的运动模式和程序不一致, 之后只有坐标的行需要加上`G1`
+    is_arc_mode: bool,
+}
+
+impl GCodeValueHandlerArcFit {
+    /// 是否是可以参与拟合的行
+    fn is_fit_line(&self, gcode_line: &GCodeLine, commands: &[GCodeCommand]) -> bool {
+        let is_xy = |params: &GCodeParams| {
+            (params.x.is_some() || params.y.is_some())
+                && GCodeParams {
+                    x: None,
+                    y: None,
+                    ..params.clone()
+                }
+                .is_empty()
+        };
+        let is_linear = match commands {
+            [GCodeCommand::Linear(params)] => is_xy(params),
+            [GCodeCommand::Modal(params)] => {
+                self.modal.motion_mode == GCodeMotionMode::Linear && is_xy(params)
+            }
+            _ => false,
+        };
+        is_linear
+            && gcode_line.comments.is_empty()
+            && !gcode_line.block_delete
+            && self.modal.distance_mode == GCodeDistanceMode::Absolute
+            && self.modal.cycle.cycle.is_none()
+            && gcode_line
+                .values
+                .iter()
+                .all(|value| matches!(value.command.as_str(), "G" | "X" | "Y"))
+    }
+
+    /// 当前位置的工件坐标, 原始数值
+    fn position(&self) -> (f64, f64) {
+        let position = self.modal.work_position();
+        let scale = self.modal.unit_scale();
+        (position.x / scale, position.y / scale)
+    }
+
+    /// 拟合并输出收集到的折线
+    fn flush(&mut self) {
+        let points = std::mem::take(&mut self.points);
+        let texts = std::mem::take(&mut self.texts);
+        if points.len() < 2 {
+            return;
+        }
+        self.writer.set_position(points[0].0, points[0].1);
+        for segment in self.fitter.fit(&points) {
+            match segment {
+                GCodeFitSegment::Line(index) => {
+                    if self.is_arc_mode {
+                        self.writer.line_to(points[index].0, points[index].1);
+                        self.is_arc_mode = false;
+                    } else {
+                        self.writer.write_line(&texts[index - 1]);
+                        self.writer.set_position(points[index].0, points[index].1);
+                    }
+                }
+                GCodeFitSegment::Arc {
+                    end,
+                    cx,
+                    cy,
+                    clockwise,
+                } => {
+                    self.writer
+                        .arc_to(points[end].0, points[end].1, cx, cy, clockwise);
+                    self.is_arc_mode = true;
+                }
+            }
+        }
+    }
+
+    /// 输出注释
+    fn write_comment(&mut self, comment: &GCodeComment) {
+        if comment.is_paren {
+            self.writer.write_line(&format!("({})", comment.text));
+        } else {
+            self.writer.write_line(&format!(";{}", comment.text));
+        }
+    }
+}
+
+impl GCodeValueHandler for GCodeValueHandlerArcFit {
+    fn handle_comment(&mut self, comment: &GCodeComment) {
+        self.comments.push(comment.clone());
+    }
+
+    fn handle_gcode_line(&mut self, gcode_line: GCodeLine) -> GCodeFlow {
+        //单独一行的注释
+        let comments = std::mem::take(&mut self.comments);
+        let line_comments = comments
+            .iter()
+            .filter(|comment| comment.span.line != gcode_line.span.line);
+        for comment in line_comments {
+            self.flush();
+            self.write_comment(comment);
+        }
+
+        let commands = gcode_line.commands();
+        if self.is_fit_line(&gcode_line, &commands) {
+            if self.points.is_empty() {
+                self.points.push(self.position());
+            }
+            self.modal.apply(&commands);
+            self.points.push(self.position());
+            self.texts.push(gcode_line.text);
+            if self.points.len() >= FIT_BUFFER_POINTS {
+                self.flush();
+            }
+            return GCodeFlow::Continue;
+        }
+
+        self.flush();
+        let have_motion = commands.iter().any(|command| {
+            matches!(
+                command,
+                GCodeCommand::Rapid(_)
+                    | GCodeCommand::Linear(_)
+                    | GCodeCommand::Arc { .. }
+                    | GCodeCommand::CubicSpline(_)
+                    | GCodeCommand::QuadraticSpline(_)
+                    | GCodeCommand::CancelMotion
+                    | GCodeCommand::Cycle { .. }
+            )
+        });
+        let have_axis = commands
+            .iter()
+            .any(|command| matches!(command, GCodeCommand::Modal(params) if params.have_axis()));
+        if self.is_arc_mode && !have_motion && have_axis {
+            //使用程序的运动模式
+            self.writer.write_line(&format!("G1 {}", gcode_line.text));
+        } else {
+            self.writer.write_line(&gcode_line.text);
+        }
+        if have_motion {
+            self.is_arc_mode = false;
+        }
+        self.modal.apply(&commands);
+        GCodeFlow::Continue
+    }
+
+    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+        let text = gcode_value_line
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+        self.handle_gcode_line(GCodeLine {
+            text,
+            values: gcode_value_line,
+            ..Default::default()
+        })
+    }
+
+    fn end(&mut self) {
+        self.flush();
+        for comment in std::mem::take(&mut self.comments) {
+            self.write_comment(&comment);
+        }
+    }
+}
+
+/// 对GCode进行圆弧拟合, 参考[GCodeValueHandlerArcFit]
+/// - [digit] 圆弧保留几位小数点
+pub fn fit_gcode_arcs(gcode: &String, fitter: &GCodeArcFitter, digit: usize) -> String {
+    let mut handler = GCodeValueHandlerArcFit {
+        fitter: fitter.clone(),
+        writer: GCodeWriter::new(digit),
+        ..Default::default()
+    };
+    GCodeParser::new(gcode).parse(&mut handler);
+    handler.writer.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arc_fit::{
+        FIT_BUFFER_POINTS, GCodeArcFitter, GCodeFitSegment, GCodeValueHandlerArcFit, fit_gcode_arcs,
+    };
+    use crate::handler::{GCodeFlow, GCodeValueHandler, GCodeValueHandlerPath};
+    use crate::parser::{GCodeLine, GCodeParser, GCodeValue};
+    use crate::{path_to_gcode, path_to_gcode_arcs};
+    use lyon_path::iterator::PathIterator;
+    use lyon_path::math::point;
+    use lyon_path::{Path, Winding};
+
+    /// 解析GCode, 返回所有的点到圆心(0,0)的最大误差和`G1`/`G2`/`G3`的行数
+    fn circle_error(gcode: &String, radius: f64) -> (f64, usize) {
+        let mut handler = GCodeValueHandlerPath::default();
+        GCodeParser::new(gcode).parse(&mut handler);
+        let mut error: f64 = 0.0;
+        for event in handler.layers[0].path.iter().flattened(0.0001) {
+            let to = event.to();
+            error = error.max(((to.x as f64).hypot(to.y as f64) - radius).abs());
+        }
+        (error, handler.layers[0].segments.len())
+    }
+
+    #[test]
+    fn test_arc_fit() {
+        let fitter = GCodeArcFitter::default();
+        //3/4圆, 之后是直线
+        let mut points: Vec<(f64, f64)> = (0..=96)
+            .map(|i| {
+                let angle = std::f64::consts::PI * 1.5 * i as f64 / 96.0;
+                (10.0 * angle.cos(), 10.0 * angle.sin())
+            })
+            .collect();
+        points.push((0.0, -20.0));
+        points.push((0.0, -30.0));
+        let segments = fitter.fit(&points);
+        assert_eq!(segments.len(), 3);
+        assert!(matches!(
+            segments[0],
+            GCodeFitSegment::Arc { end: 96, clockwise: false, cx, cy }
+                if cx.abs() < 1e-6 && cy.abs() < 1e-6
+        ));
+        assert_eq!(segments[1], GCodeFitSegment::Line(97));
+
+        //限制一个圆弧覆盖的点数
+        let fitter = GCodeArcFitter {
+            max_segments: 20,
+            ..Default::default()
+        };
+        let segments = fitter.fit(&points);
+        let mut start = 0;
+        for segment in &segments[..segments.len() - 2] {
+            let GCodeFitSegment::Arc { end, .. } = *segment else {
+                panic!("{:?}", segment);
+            };
+            assert!(end - start <= 20);
+            start = end;
+        }
+        assert_eq!(start, 96);
+
+        //折线
+        let points = vec![(0.0, 0.0), (1.0, 1.0), (2.0, 0.0), (3.0, 1.0), (4.0, 0.0)];
+        assert!(
+            fitter
+                .fit(&points)
+                .iter()
+                .all(|segment| matches!(segment, GCodeFitSegment::Line(_)))
+        );
+    }
+
+    #[test]
+    fn test_arc_fit_gcode() {
+        let mut builder = Path::builder();
+        builder.add_circle(point(0.0, 0.0), 20.0, Winding::Positive);
+        let path = builder.build();
+
+        let gcode = path_to_gcode(&path, 0.005, 4, &"G90 G21".to_string());
+        let (error, count) = circle_error(&gcode, 20.0);
+        assert!(error < 0.011, "{}", error);
+
+        let fitter = GCodeArcFitter::default();
+        let fitted = fit_gcode_arcs(&gcode, &fitter, 4);
+        assert!(fitted.starts_with("G90 G21\nG0 X-20 Y0\n"));
+        let (fitted_error, fitted_count) = circle_error(&fitted, 20.0);
+        assert!(fitted_error < 0.02, "{}", fitted_error);
+        assert!(fitted_count * 10 < count, "{} {}", fitted_count, count);
+
+        let arcs = path_to_gcode_arcs(&path, 0.01, 4, "G90 G21");
+        assert!(arcs.starts_with("G90 G21\nG0 X-20 Y0\n"));
+        let (arcs_error, arcs_count) = circle_error(&arcs, 20.0);
+        assert!(arcs_error < 0.02, "{}", arcs_error);
+        assert!(arcs_count * 10 < count, "{} {}", arcs_count, count);
+
+        //注释和其他的行原样输出, 之后只有坐标的行使用`G1`
+        let gcode = "G0 X10 Y0\n;arc\nG1 X9.9452 Y1.0453\nX9.7815 Y2.0791\nX9.5106 Y3.0902\nX9.1355 Y4.0674\nM5 (off)\nX0 Y0".to_string();
+        let fitter = GCodeArcFitter {
+            tolerance: 0.02,
+            ..Default::default()
+        };
+        let fitted = fit_gcode_arcs(&gcode, &fitter, 4);
+        assert_eq!(
+            fitted,
+            "G0 X10 Y0\n;arc\nG3 X9.1355 Y4.0674 I-10.0004 J0.0001\nM5 (off)\nG1 X0 Y0"
+        );
+    }
+    #[test]
+    fn test_arc_fit_block_delete() {
+        //跳段行原样输出, 保留`/`
+        let mut lines = vec!["G0 X10 Y0".to_string()];
+        for i in 1..=20 {
+            let angle = i as f64 / 20.0;
+            lines.push(format!(
+                "/G1 X{:.4} Y{:.4}",
+                10.0 * angle.cos(),
+                10.0 * angle.sin()
+            ));
+        }
+        let gcode = lines.join("\n");
+        let fitted = fit_gcode_arcs(&gcode, &GCodeArcFitter::default(), 4);
+        assert_eq!(fitted, gcode);
+    }
+
+    /// 检查收集的点数
+    #[derive(Default)]
+    struct BufferHandler {
+        handler: GCodeValueHandlerArcFit,
+        max_points: usize,
+    }
+
+    impl GCodeValueHandler for BufferHandler {
+        fn handle_gcode_line(&mut self, gcode_line: GCodeLine) -> GCodeFlow {
+            let flow = self.handler.handle_gcode_line(gcode_line);
+            self.max_points = self.max_points.max(self.handler.points.len());
+            flow
+        }
+
+        fn handle_gcode_value(&mut self, _gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+            GCodeFlow::Continue
+        }
+
+        fn end(&mut self) {
+            self.handler.end();
+        }
+    }
+
+    #[test]
+    fn test_arc_fit_buffer() {
+        //很长的折线, 分批拟合
+        let mut builder = Path::builder();
+        builder.add_circle(point(0.0, 0.0), 50.0, Winding::Positive);
+        let path = builder.build();
+        let gcode = path_to_gcode(&path, 0.00002, 4, &"G90 G21".to_string());
+        let (_, count) = circle_error(&gcode, 50.0);
+        assert!(count > FIT_BUFFER_POINTS * 2, "{}", count);
+
+        let mut handler = BufferHandler::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert!(handler.max_points < FIT_BUFFER_POINTS);
+        let fitted = handler.handler.writer.to_string();
+        let (fitted_error, fitted_count) = circle_error(&fitted, 50.0);
+        assert!(fitted_error < 0.02, "{}", fitted_error);
+        assert!(fitted_count * 10 < count, "{} {}", fitted_count, count);
+    }
+}
@@ -0,0 +1,96 @@
+///
+/// @author <a href="mailto: angcyo@126.com">angcyo</a>
+/// @date 2026/10/18
+///
+/// Marlin/RepRap 串口协议的行号和校验和
+/// - `N123 G1 X1*57`
+///
+/// 计算一行数据的校验和
+/// - 对`*`之前的所有字节进行异或
+pub fn gcode_checksum(line: &str) -> u8 {
+    line.bytes().fold(0, |checksum, b| checksum ^ b)
+}
+
+/// 拆分一行数据中的指令和注释
+/// - `()`注释和`;`注释都放到注释中, 按照原来的顺序使用空格连接
+/// - `G1 X1 (move) Y2 ;end` -> (`G1 X1 Y2`, `(move) ;end`)
+pub fn gcode_split_comments(line: &str) -> (String, String) {
+    let mut codes = vec![];
+    let mut comments = vec![];
+    let mut rest = line;
+    loop {
+        match rest.find(['(', ';']) {
+            Some(index) if rest[index..].starts_with('(') => {
+                codes.push(&rest[..index]);
+                let end = rest[index..]
+                    .find(')')
+                    .map_or(rest.len(), |i| index + i + 1);
+                comments.push(&rest[index..end]);
+                rest = &rest[end..];
+            }
+            Some(index) => {
+                codes.push(&rest[..index]);
+                comments.push(&rest[index..]);
+                break;
+            }
+            None => {
+                codes.push(rest);
+                break;
+            }
+        }
+    }
+    let join = |parts: Vec<&str>| {
+        parts
+            .iter()
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    };
+    (join(codes), join(comments))
+}
+
+/// 给一行数据加上行号和校验和
+/// - `G1 X1` -> `N1 G1 X1*xx`
+/// - `()`/`;`注释放在校验和之后, `G1 X1 ;comment` -> `N1 G1 X1*xx ;comment`
+/// - [line_number] 行号
+pub fn gcode_checksum_line(line_number: usize, line: &str) -> String {
+    let (code, comment) = gcode_split_comments(line);
+    let line = format!("N{} {}", line_number, code);
+    let checksum = gcode_checksum(&line);
+    if comment.is_empty() {
+        format!("{}*{}", line, checksum)
+    } else {
+        format!("{}*{} {}", line, checksum, comment)
+    }
+}
+
+/// 重置行号的指令`M110`
+/// - 之后的第一行行号为`line_number + 1`
+pub fn gcode_line_number_reset(line_number: usize) -> String {
+    gcode_checksum_line(line_number, &format!("M110 N{}", line_number))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checksum::{
+        gcode_checksum, gcode_checksum_line, gcode_line_number_reset, gcode_split_comments,
+    };
+
+    #[test]
+    fn test_gcode_checksum() {
+        assert_eq!(gcode_checksum("N1 G1 X1"), 96);
+        assert_eq!(gcode_checksum_line(0, "M110 N0"), "N0 M110 N0*125");
+        assert_eq!(gcode_line_number_reset(0), "N0 M110 N0*125");
+        assert_eq!(gcode_checksum_line(1, "G1 X1 ;move"), "N1 G1 X1*96 ;move");
+        assert_eq!(gcode_checksum_line(1, "G1 X1 (move)"), "N1 G1 X1*96 (move)");
+        assert_eq!(
+            gcode_split_comments("G1 X1 (move) Y2 ;end (x)"),
+            ("G1 X1 Y2".to_string(), "(move) ;end (x)".to_string())
+        );
+        assert_eq!(
+            gcode_split_comments("(header"),
+            ("".to_string(), "(header".to_string())
+        );
+    }
+}
@@ -0,0 +1,637 @@
+use crate::parser::GCodeValue;
+use crate::writer::format_number;
+
+///
+/// @author <a href="mailto: angcyo@126.com">angcyo</a>
+/// @date 2026/10/18
+///
+/// 类型化的GCode指令
+/// - 由一行[GCodeValue]解析而来, 一行可能包含多个指令, 比如`G90 G21 G1 X10`
+/// - 使用[std::fmt::Display]输出回GCode文本
+#[derive(Clone, Debug, PartialEq)]
+pub enum GCodeCommand {
+    /// `G0` 快速移动
+    Rapid(GCodeParams),
+    /// `G1` 直线插补
+    Linear(GCodeParams),
+    /// `G2` 顺时针圆弧 / `G3` 逆时针圆弧
+    Arc {
+        /// 是否顺时针
+        clockwise: bool,
+        params: GCodeParams,
+    },
+    /// `G5` 三次B样条, `I`/`J`为第一个控制点相对于起点的偏移, `P`/`Q`为第二个控制点相对于终点的偏移
+    /// - 没有`I`/`J`时, 第一个控制点为上一段`G5`第二个控制点的对称点
+    CubicSpline(GCodeParams),
+    /// `G5.1` 二次B样条, `I`/`J`为控制点相对于起点的偏移
+    QuadraticSpline(GCodeParams),
+    /// `G4` 暂停
+    /// - GRBL/LinuxCNC 的`P`单位是秒, Marlin 的`P`单位是毫秒, `S`单位是秒
+    Dwell(GCodeParams),
+    /// `G17` / `G18` / `G19` 圆弧平面
+    Plane(GCodePlane),
+    /// `G20` 英寸 / `G21` 毫米
+    Units(GCodeUnits),
+    /// `G90` 绝对坐标 / `G91` 相对坐标
+    DistanceMode(GCodeDistanceMode),
+    /// `M82` 挤出轴绝对坐标 / `M83` 挤出轴相对坐标
+    ExtrusionMode(GCodeDistanceMode),
+    /// `G93` / `G94` / `G95` 进给速度模式
+    FeedMode(GCodeFeedMode),
+    /// `G80` 取消运动模式, 也会取消固定循环
+    CancelMotion,
+    /// `G73` / `G81`~`G89` 固定循环
+    /// - `Z`孔底 / `R`安全平面 / `Q`每次啄钻的深度 / `P`孔底暂停 / `L`重复次数
+    /// - 之后只有坐标的行会在新的位置重复循环, 直到`G80`或者其他运动指令
+    Cycle {
+        cycle: GCodeCycle,
+        params: GCodeParams,
+    },
+    /// `G98` / `G99` 固定循环的退刀模式
+    CycleReturn(GCodeCycleReturn),
+    /// `G53` 同一行的运动使用机床坐标, 非模态
+    MachineCoordinates,
+    /// `G54`~`G59` 选择工件坐标系, 0表示`G54`
+    CoordinateSystem(usize),
+    /// `G92` 设置当前位置在工件坐标系中的坐标
+    SetPosition(GCodeParams),
+    /// `G92.1` 清除`G92`的偏移
+    ResetPosition,
+    /// `G28` 回到原点, 有坐标轴参数时只有对应的轴回原点
+    Home(GCodeParams),
+    /// `M3` 顺时针主轴/恒定功率激光 / `M4` 逆时针主轴/动态功率激光
+    SpindleOn {
+        /// 是否顺时针
+        clockwise: bool,
+        /// 转速/功率
+        s: Option<f64>,
+    },
+    /// `M5` 主轴/激光关闭
+    SpindleOff,
+    /// `T` 选择刀具
+    ToolSelect(u32),
+    /// `M6` 换刀, 同一行中有`T`时为对应的刀具
+    ToolChange(Option<u32>),
+    /// 没有运动指令的参数, 使用当前的模态
+    /// - `X10 Y5` 使用上一次的运动模式移动
+    /// - `F1000` / `S500` 设置进给速度/功率
+    Modal(GCodeParams),
+    /// 其他未识别的指令, 包含指令本身和它使用的参数
+    Other(Vec<GCodeValue>),
+}
+
+/// 指令参数, 没有出现的参数为[None]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GCodeParams {
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    pub z: Option<f64>,
+    pub a: Option<f64>,
+    pub b: Option<f64>,
+    pub c: Option<f64>,
+    /// 挤出轴
+    pub e: Option<f64>,
+    /// 圆心相对于起点的偏移
+    pub i: Option<f64>,
+    pub j: Option<f64>,
+    pub k: Option<f64>,
+    /// 圆弧半径
+    pub r: Option<f64>,
+    pub p: Option<f64>,
+    pub q: Option<f64>,
+    /// 固定循环的重复次数
+    pub l: Option<f64>,
+    /// 进给速度
+    pub f: Option<f64>,
+    /// 主轴转速/激光功率
+    pub s: Option<f64>,
+}
+
+/// 圆弧平面
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GCodePlane {
+    /// `G17`
+    #[default]
+    XY,
+    /// `G18`
+    ZX,
+    /// `G19`
+    YZ,
+}
+
+/// 数值单位
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GCodeUnits {
+    /// `G20`
+    Inches,
+    /// `G21`
+    #[default]
+    Millimeters,
+}
+
+/// 坐标模式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GCodeDistanceMode {
+    /// `G90`
+    #[default]
+    Absolute,
+    /// `G91`
+    Relative,
+}
+
+/// 固定循环
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GCodeCycle {
+    /// `G73` 断屑钻孔, 每次啄钻后少量回退
+    ChipBreak,
+    /// `G81` 钻孔
+    Drill,
+    /// `G82` 钻孔, 孔底暂停
+    DrillDwell,
+    /// `G83` 啄钻, 每次啄钻后退回安全平面
+    Peck,
+    /// `G84` 攻丝
+    Tap,
+    /// `G85` 镗孔, 进给退出
+    Bore,
+    /// `G86` 镗孔, 主轴停止后快速退出
+    BoreSpindleStop,
+    /// `G87` 背镗, `I`/`J`为让刀的偏移, `K`为背镗的顶部
+    BackBore,
+    /// `G88` 镗孔, 孔底暂停后主轴停止, 手动退出
+    BoreManual,
+    /// `G89` 镗孔, 孔底暂停后进给退出
+    BoreDwell,
+}
+
+/// 固定循环的退刀模式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GCodeCycleReturn {
+    /// `G98` 退回到开始循环时的Z, 低于`R`时退回到`R`
+    #[default]
+    Initial,
+    /// `G99` 退回到`R`
+    RPlane,
+}
+
+impl GCodeCycle {
+    /// 从`G`指令的数值解析
+    pub fn from_code(code: f64) -> Option<Self> {
+        let cycle = match code {
+            73.0 => Self::ChipBreak,
+            81.0 => Self::Drill,
+            82.0 => Self::DrillDwell,
+            83.0 => Self::Peck,
+            84.0 => Self::Tap,
+            85.0 => Self::Bore,
+            86.0 => Self::BoreSpindleStop,
+            87.0 => Self::BackBore,
+            88.0 => Self::BoreManual,
+            89.0 => Self::BoreDwell,
+            _ => return None,
+        };
+        Some(cycle)
+    }
+
+    /// 对应的`G`指令, 比如`G81`
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ChipBreak => "G73",
+            Self::Drill => "G81",
+            Self::DrillDwell => "G82",
+            Self::Peck => "G83",
+            Self::Tap => "G84",
+            Self::Bore => "G85",
+            Self::BoreSpindleStop => "G86",
+            Self::BackBore => "G87",
+            Self::BoreManual => "G88",
+            Self::BoreDwell => "G89",
+        }
+    }
+}
+
+/// 进给速度模式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GCodeFeedMode {
+    /// `G93` 反比时间, 在`1/F`分钟内完成运动
+    InverseTime,
+    /// `G94` 每分钟进给
+    #[default]
+    UnitsPerMinute,
+    /// `G95` 每转进给
+    UnitsPerRevolution,
+}
+
+impl GCodeParams {
+    /// 参数字母和对应的值, 按照输出顺序
+    fn letters(&self) -> [(char, Option<f64>); 16] {
+        [
+            ('X', self.x),
+            ('Y', self.y),
+            ('Z', self.z),
+            ('A', self.a),
+            ('B', self.b),
+            ('C', self.c),
+            ('E', self.e),
+            ('I', self.i),
+            ('J', self.j),
+            ('K', self.k),
+            ('R', self.r),
+            ('P', self.p),
+            ('Q', self.q),
+            ('L', self.l),
+            ('F', self.f),
+            ('S', self.s),
+        ]
+    }
+
+    /// 设置参数, 不支持的字母返回false
+    fn set(&mut self, letter: &str, value: f64) -> bool {
+        let param = match letter {
+            "X" => &mut self.x,
+            "Y" => &mut self.y,
+            "Z" => &mut self.z,
+            "A" => &mut self.a,
+            "B" => &mut self.b,
+            "C" => &mut self.c,
+            "E" => &mut self.e,
+            "I" => &mut self.i,
+            "J" => &mut self.j,
+            "K" => &mut self.k,
+            "R" => &mut self.r,
+            "P" => &mut self.p,
+            "Q" => &mut self.q,
+            "L" => &mut self.l,
+            "F" => &mut self.f,
+            "S" => &mut self.s,
+            _ => return false,
+        };
+        *param = Some(value);
+        true
+    }
+
+    /// 是否有坐标轴参数
+    pub fn have_axis(&self) -> bool {
+        [self.x, self.y, self.z, self.a, self.b, self.c, self.e]
+            .iter()
+            .any(|v| v.is_some())
+    }
+
+    /// 是否没有任何参数
+    pub fn is_empty(&self) -> bool {
+        self.letters().iter().all(|(_, v)| v.is_none())
+    }
+
+    /// 转换成[GCodeValue]
+    /// - [digit] 保留几位小数点
+    pub fn to_values(&self, digit: usize) -> Vec<GCodeValue> {
+        self.letters()
+            .iter()
+            .filter_map(|(letter, value)| {
+                value.map(|value| {
+                    let mut gcode_value = GCodeValue::new();
+                    gcode_value.command = letter.to_string();
+                    gcode_value.value = format_number(value, digit);
+                    gcode_value
+                })
+            })
+            .collect()
+    }
+
+    /// 输出成GCode参数, 比如`X10 Y5 F1000`
+    /// - [digit] 保留几位小数点
+    pub fn to_gcode(&self, digit: usize) -> String {
+        self.to_values(digit)
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+impl GCodeCommand {
+    /// 从一行[GCodeValue]中解析出所有的指令
+    /// - 坐标参数只属于行中的运动指令/固定循环/`G92`/`G28`或者未识别的`G`指令(比如`G10`)
+    /// - `N`行号和`*`校验和会被忽略
+    pub fn from_values(values: &[GCodeValue]) -> Vec<GCodeCommand> {
+        let mut params = GCodeParams::default();
+        //[GCodeParams]不支持的参数
+        let mut other_params: Vec<GCodeValue> = vec![];
+        //指令
+        let mut codes: Vec<&GCodeValue> = vec![];
+        for value in values {
+            match value.command.as_str() {
+                "G" | "M" | "T" => codes.push(value),
+                "N" | "*" => {}
+                letter => {
+                    if !params.set(letter, value.value_f64()) {
+                        other_params.push(value.clone());
+                    }
+                }
+            }
+        }
+
+        let tool = codes
+            .iter()
+            .find(|code| code.command == "T")
+            .map(|code| code.value_f64() as u32);
+        let have_tool_change = codes
+            .iter()
+            .any(|code| code.command == "M" && code.value_f64() == 6.0);
+        //`S`只属于主轴指令, 不会同时出现在运动的参数中
+        let have_spindle_on = codes
+            .iter()
+            .any(|code| code.command == "M" && matches!(code.value_f64(), 3.0 | 4.0));
+        let spindle_s = if have_spindle_on {
+            params.s.take()
+        } else {
+            None
+        };
+        //参数是否被指令使用了
+        let mut params_used = false;
+
+        let mut commands = vec![];
+        for code in codes {
+            let command = match (code.command.as_str(), code.value_f64()) {
+                ("G", 0.0) => Self::Rapid(params.clone()),
+                ("G", 1.0) => Self::Linear(params.clone()),
+                ("G", 2.0) => Self::Arc {
+                    clockwise: true,
+                    params: params.clone(),
+                },
+                ("G", 3.0) => Self::Arc {
+                    clockwise: false,
+                    params: params.clone(),
+                },
+                ("G", 4.0) => Self::Dwell(params.clone()),
+                ("G", 5.0) => Self::CubicSpline(params.clone()),
+                ("G", 5.1) => Self::QuadraticSpline(params.clone()),
+                ("G", 17.0) => Self::Plane(GCodePlane::XY),
+                ("G", 18.0) => Self::Plane(GCodePlane::ZX),
+                ("G", 19.0) => Self::Plane(GCodePlane::YZ),
+                ("G", 20.0) => Self::Units(GCodeUnits::Inches),
+                ("G", 21.0) => Self::Units(GCodeUnits::Millimeters),
+                ("G", 90.0) => Self::DistanceMode(GCodeDistanceMode::Absolute),
+                ("G", 91.0) => Self::DistanceMode(GCodeDistanceMode::Relative),
+                ("G", 93.0) => Self::FeedMode(GCodeFeedMode::InverseTime),
+                ("G", 94.0) => Self::FeedMode(GCodeFeedMode::UnitsPerMinute),
+                ("G", 95.0) => Self::FeedMode(GCodeFeedMode::UnitsPerRevolution),
+                ("G", 80.0) => Self::CancelMotion,
+                ("G", value) if GCodeCycle::from_code(value).is_some() => Self::Cycle {
+                    cycle: GCodeCycle::from_code(value).unwrap(),
+                    params: params.clone(),
+                },
+                ("G", 98.0) => Self::CycleReturn(GCodeCycleReturn::Initial),
+                ("G", 99.0) => Self::CycleReturn(GCodeCycleReturn::RPlane),
+                ("G", 53.0) => Self::MachineCoordinates,
+                ("G", value) if (54.0..=59.0).contains(&value) && value.fract() == 0.0 => {
+                    Self::CoordinateSystem(value as usize - 54)
+                }
+                ("G", 92.0) => Self::SetPosition(params.clone()),
+                ("G", 92.1) => Self::ResetPosition,
+                ("G", 28.0) => Self::Home(params.clone()),
+                ("M", 3.0) | ("M", 4.0) => Self::SpindleOn {
+                    clockwise: code.value_f64() == 3.0,
+                    s: spindle_s,
+                },
+                ("M", 5.0) => Self::SpindleOff,
+                ("M", 82.0) => Self::ExtrusionMode(GCodeDistanceMode::Absolute),
+                ("M", 83.0) => Self::ExtrusionMode(GCodeDistanceMode::Relative),
+                ("M", 6.0) => Self::ToolChange(tool),
+                ("T", value) => {
+                    if have_tool_change {
+                        continue;
+                    }
+                    Self::ToolSelect(value as u32)
+                }
+                ("G", _) if !params_used => {
+                    params_used = true;
+                    let mut values = vec![code.clone()];
+                    values.extend(params.to_values(6));
+                    values.extend(other_params.iter().cloned());
+                    Self::Other(values)
+                }
+                _ => Self::Other(vec![code.clone()]),
+            };
+            if let Self::Rapid(_)
+            | Self::Linear(_)
+            | Self::Arc { .. }
+            | Self::CubicSpline(_)
+            | Self::QuadraticSpline(_)
+            | Self::Cycle { .. }
+            | Self::Dwell(_)
+            | Self::SetPosition(_)
+            | Self::Home(_) = command
+            {
+                params_used = true;
+            }
+            commands.push(command);
+        }
+
+        if !params_used {
+            if !other_params.is_empty() {
+                let mut values = params.to_values(6);
+                values.extend(other_params);
+                commands.push(Self::Other(values));
+            } else if !params.is_empty() {
+                commands.push(Self::Modal(params));
+            }
+        }
+        commands
+    }
+
+    /// 输出成GCode文本
+    /// - [digit] 保留几位小数点
+    pub fn to_gcode(&self, digit: usize) -> String {
+        let with_params = |code: &str, params: &GCodeParams| {
+            let params = params.to_gcode(digit);
+            if params.is_empty() {
+                code.to_string()
+            } else {
+                format!("{} {}", code, params)
+            }
+        };
+        match self {
+            Self::Rapid(params) => with_params("G0", params),
+            Self::Linear(params) => with_params("G1", params),
+            Self::Arc { clockwise, params } => {
+                with_params(if *clockwise { "G2" } else { "G3" }, params)
+            }
+            Self::CubicSpline(params) => with_params("G5", params),
+            Self::QuadraticSpline(params) => with_params("G5.1", params),
+            Self::Dwell(params) => with_params("G4", params),
+            Self::Plane(plane) => match plane {
+                GCodePlane::XY => "G17",
+                GCodePlane::ZX => "G18",
+                GCodePlane::YZ => "G19",
+            }
+            .to_string(),
+            Self::Units(units) => match units {
+                GCodeUnits::Inches => "G20",
+                GCodeUnits::Millimeters => "G21",
+            }
+            .to_string(),
+            Self::DistanceMode(mode) => match mode {
+                GCodeDistanceMode::Absolute => "G90",
+                GCodeDistanceMode::Relative => "G91",
+            }
+            .to_string(),
+            Self::ExtrusionMode(mode) => match mode {
+                GCodeDistanceMode::Absolute => "M82",
+                GCodeDistanceMode::Relative => "M83",
+            }
+            .to_string(),
+            Self::SpindleOn { clockwise, s } => {
+                let code = if *clockwise { "M3" } else { "M4" };
+                match s {
+                    Some(s) => format!("{} S{}", code, format_number(*s, digit)),
+                    None => code.to_string(),
+                }
+            }
+            Self::FeedMode(mode) => match mode {
+                GCodeFeedMode::InverseTime => "G93",
+                GCodeFeedMode::UnitsPerMinute => "G94",
+                GCodeFeedMode::UnitsPerRevolution => "G95",
+            }
+            .to_string(),
+            Self::CancelMotion => "G80".to_string(),
+            Self::Cycle { cycle, params } => with_params(cycle.code(), params),
+            Self::CycleReturn(mode) => match mode {
+                GCodeCycleReturn::Initial => "G98",
+                GCodeCycleReturn::RPlane => "G99",
+            }
+            .to_string(),
+            Self::MachineCoordinates => "G53".to_string(),
+            Self::CoordinateSystem(index) => format!("G{}", 54 + index),
+            Self::SetPosition(params) => with_params("G92", params),
+            Self::ResetPosition => "G92.1".to_string(),
+            Self::Home(params) => with_params("G28", params),
+            Self::SpindleOff => "M5".to_string(),
+            Self::ToolSelect(tool) => format!("T{}", tool),
+            Self::ToolChange(tool) => match tool {
+                Some(tool) => format!("T{} M6", tool),
+                None => "M6".to_string(),
+            },
+            Self::Modal(params) => params.to_gcode(digit),
+            Self::Other(values) => values
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<String>>()
+                .join(" "),
+        }
+    }
+}
+
+/// 实现[Display], 保留6位小数
+impl std::fmt::Display for GCodeCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_gcode(6))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::{GCodeCommand, GCodeDistanceMode, GCodeParams, GCodeUnits};
+    use crate::handler::{GCodeFlow, GCodeValueHandler};
+    use crate::parser::{GCodeLine, GCodeParser, GCodeValue};
+
+    /// 收集所有指令
+    #[derive(Default)]
+    struct CommandsHandler {
+        commands: Vec<Vec<GCodeCommand>>,
+    }
+
+    impl GCodeValueHandler for CommandsHandler {
+        fn handle_gcode_line(&mut self, gcode_line: GCodeLine) -> GCodeFlow {
+            self.commands.push(gcode_line.commands());
+            GCodeFlow::Continue
+        }
+
+        fn handle_gcode_value(&mut self, _gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+            GCodeFlow::Continue
+        }
+    }
+
+    fn parse(gcode: &str) -> Vec<Vec<GCodeCommand>> {
+        let gcode = gcode.to_string();
+        let mut handler = CommandsHandler::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        handler.commands
+    }
+
+    #[test]
+    fn test_gcode_command() {
+        let commands = parse(
+            "N1 G90 G21 G01 X10 Y-5.5 F1200*12\nM3 S1000\nT2 M6\nG92 X0 Y0\nX5\nG3 X1 Y1 I0.5 J0",
+        );
+        assert_eq!(
+            commands[0],
+            vec![
+                GCodeCommand::DistanceMode(GCodeDistanceMode::Absolute),
+                GCodeCommand::Units(GCodeUnits::Millimeters),
+                GCodeCommand::Linear(GCodeParams {
+                    x: Some(10.0),
+                    y: Some(-5.5),
+                    f: Some(1200.0),
+                    ..Default::default()
+                }),
+            ]
+        );
+        assert_eq!(
+            commands[1],
+            vec![GCodeCommand::SpindleOn {
+                clockwise: true,
+                s: Some(1000.0)
+            }]
+        );
+        assert_eq!(commands[2], vec![GCodeCommand::ToolChange(Some(2))]);
+        assert_eq!(
+            commands[3],
+            vec![GCodeCommand::SetPosition(GCodeParams {
+                x: Some(0.0),
+                y: Some(0.0),
+                ..Default::default()
+            })]
+        );
+        assert!(matches!(&commands[4][0], GCodeCommand::Modal(params) if params.x == Some(5.0)));
+
+        let output: Vec<String> = commands
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            })
+            .collect();
+        assert_eq!(
+            output,
+            vec![
+                "G90 G21 G1 X10 Y-5.5 F1200",
+                "M3 S1000",
+                "T2 M6",
+                "G92 X0 Y0",
+                "X5",
+                "G3 X1 Y1 I0.5 J0"
+            ]
+        );
+
+        //`S`只属于主轴指令, 不会输出两次
+        let commands = parse("G1 X10 S1000 M3");
+        assert_eq!(
+            commands[0],
+            vec![
+                GCodeCommand::Linear(GCodeParams {
+                    x: Some(10.0),
+                    ..Default::default()
+                }),
+                GCodeCommand::SpindleOn {
+                    clockwise: true,
+                    s: Some(1000.0)
+                },
+            ]
+        );
+        let output: Vec<String> = commands[0].iter().map(|c| c.to_string()).collect();
+        assert_eq!(output.join(" "), "G1 X10 M3 S1000");
+    }
+}
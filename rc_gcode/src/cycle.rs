@@ -0,0 +1,466 @@
+use crate::command::{GCodeCommand, GCodeCycle, GCodeCycleReturn, GCodeParams};
+use crate::handler::{GCodeFlow, GCodeValueHandler};
+use crate::modal::{GCodeModalState, GCodeSpindleState};
+use crate::parser::{GCodeComment, GCodeParser, GCodeValue};
+use crate::writer::GCodeWriter;
+
+///
+/// @author <a href="mailto: angcyo@126.com">angcyo</a>
+/// @date 2026/10/18
+///
+/// 固定循环的状态, 在行与行之间保持
+/// - 参数`Z`/`R`/`Q`/`P`/`I`/`J`/`K`会一直保持, 之后只有坐标的行使用保持的参数
+#[derive(Clone, Debug, Default)]
+pub struct GCodeCycleState {
+    /// 当前的固定循环, `G80`或者其他运动指令会取消
+    pub cycle: Option<GCodeCycle>,
+    /// 退刀模式
+    pub retract: GCodeCycleReturn,
+    /// 保持的参数, 原始数值
+    pub params: GCodeParams,
+    /// 开始循环时的Z, 工件坐标, 原始数值
+    pub initial_z: Option<f64>,
+}
+
+/// 啄钻时距离上一次孔底的安全距离, 单位mm
+/// - LinuxCNC使用0.010inch
+const CYCLE_CLEARANCE: f64 = 0.254;
+
+/// 一个孔的参数, 工件坐标, 原始数值
+struct GCodeCycleHole {
+    x: f64,
+    y: f64,
+    /// 孔底
+    z: f64,
+    /// 安全平面
+    r: f64,
+    /// 退刀的高度
+    clear_z: f64,
+    /// 每次啄钻的深度
+    q: Option<f64>,
+    /// 孔底暂停
+    p: Option<f64>,
+    /// 啄钻回退的距离
+    clearance: f64,
+    /// 背镗让刀的偏移
+    i: f64,
+    j: f64,
+    /// 背镗的顶部
+    k: f64,
+    /// 重新启动主轴的指令, 主轴关闭时为[None]
+    spindle: Option<GCodeCommand>,
+}
+
+/// 展开固定循环输出的运动, 每一个指令为一行
+struct GCodeCycleMotion {
+    lines: Vec<Vec<GCodeCommand>>,
+    /// 当前的位置, 工件坐标, 原始数值
+    x: f64,
+    y: f64,
+    z: f64,
+    /// 是否是相对坐标
+    relative: bool,
+}
+
+impl GCodeCycleState {
+    /// 展开一行指令, 参考[GCodeModalState::expand_cycle]
+    /// - [modal] 这一行之前的模态状态
+    pub fn expand(
+        &mut self,
+        modal: &GCodeModalState,
+        commands: &[GCodeCommand],
+    ) -> Vec<Vec<GCodeCommand>> {
+        //这一行中除了固定循环之外的指令
+        let mut line = vec![];
+        //这一行中孔的参数
+        let mut hole: Option<&GCodeParams> = None;
+        for command in commands {
+            match command {
+                GCodeCommand::Cycle { cycle, params } => {
+                    self.cycle = Some(*cycle);
+                    hole = Some(params);
+                }
+                GCodeCommand::Modal(params) if self.cycle.is_some() && params.have_axis() => {
+                    hole = Some(params);
+                }
+                GCodeCommand::CycleReturn(mode) => {
+                    self.retract = *mode;
+                    line.push(command.clone());
+                }
+                GCodeCommand::Rapid(_)
+                | GCodeCommand::Linear(_)
+                | GCodeCommand::Arc { .. }
+                | GCodeCommand::CubicSpline(_)
+                | GCodeCommand::QuadraticSpline(_)
+                | GCodeCommand::CancelMotion => {
+                    self.cycle = None;
+                    self.initial_z = None;
+                    line.push(command.clone());
+                }
+                _ => line.push(command.clone()),
+            }
+        }
+        let (Some(cycle), Some(params)) = (self.cycle, hole) else {
+            return vec![commands.to_vec()];
+        };
+        if params.f.is_some() || params.s.is_some() {
+            line.push(GCodeCommand::Modal(GCodeParams {
+                f: params.f,
+                s: params.s,
+                ..Default::default()
+            }));
+        }
+        let sticky = &mut self.params;
+        sticky.z = params.z.or(sticky.z);
+        sticky.r = params.r.or(sticky.r);
+        sticky.q = params.q.or(sticky.q);
+        sticky.p = params.p.or(sticky.p);
+        sticky.i = params.i.or(sticky.i);
+        sticky.j = params.j.or(sticky.j);
+        sticky.k = params.k.or(sticky.k);
+
+        //这一行的模态指令生效之后的状态
+        let mut state = modal.clone();
+        state.apply(&line);
+        let scale = state.unit_scale();
+        let origin = state.work_offset();
+        let mut motion = GCodeCycleMotion {
+            lines: vec![],
+            x: (state.position.x - origin.x) / scale,
+            y: (state.position.y - origin.y) / scale,
+            z: (state.position.z - origin.z) / scale,
+            relative: state.is_relative(),
+        };
+        let mut lines = vec![];
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        let (Some(r), Some(z)) = (self.params.r, self.params.z) else {
+            //缺少参数, 无法钻孔
+            return lines;
+        };
+
+        //相对坐标时, `R`相对于当前的Z, `Z`/`K`相对于`R`
+        let start_z = motion.z;
+        let initial_z = *self.initial_z.get_or_insert(start_z);
+        let (r, z) = if motion.relative {
+            (start_z + r, start_z + r + z)
+        } else {
+            (r, z)
+        };
+        let k = match self.params.k {
+            Some(k) if motion.relative => r + k,
+            Some(k) => k,
+            None => r,
+        };
+        let clear_z = match self.retract {
+            GCodeCycleReturn::Initial => initial_z.max(r),
+            GCodeCycleReturn::RPlane => r,
+        };
+        let spindle = match state.spindle {
+            GCodeSpindleState::Off => None,
+            spindle => Some(GCodeCommand::SpindleOn {
+                clockwise: spindle == GCodeSpindleState::Clockwise,
+                s: None,
+            }),
+        };
+
+        //预备运动, 低于`R`时先退到`R`
+        if motion.z < r {
+            motion.rapid(None, None, Some(r));
+        }
+        let repeat = params.l.map_or(1, |l| l.max(0.0) as usize);
+        for _ in 0..repeat {
+            let (x, y) = if motion.relative {
+                (
+                    motion.x + params.x.unwrap_or(0.0),
+                    motion.y + params.y.unwrap_or(0.0),
+                )
+            } else {
+                (params.x.unwrap_or(motion.x), params.y.unwrap_or(motion.y))
+            };
+            motion.drill(
+                cycle,
+                &GCodeCycleHole {
+                    x,
+                    y,
+                    z,
+                    r,
+                    clear_z,
+                    q: self.params.q,
+                    p: self.params.p,
+                    clearance: CYCLE_CLEARANCE / scale,
+                    i: self.params.i.unwrap_or(0.0),
+                    j: self.params.j.unwrap_or(0.0),
+                    k,
+                    spindle: spindle.clone(),
+                },
+            );
+        }
+        lines.extend(motion.lines);
+        lines
+    }
+}
+
+impl GCodeCycleMotion {
+    /// 输出一行指令
+    fn push(&mut self, command: GCodeCommand) {
+        self.lines.push(vec![command]);
+    }
+
+    /// 移动到指定位置, 没有变化的坐标不输出
+    /// - [linear] 是否是`G1`进给, 否则为`G0`快速移动
+    fn move_to(&mut self, linear: bool, x: Option<f64>, y: Option<f64>, z: Option<f64>) {
+        let relative = self.relative;
+        let axis = |value: Option<f64>, current: &mut f64| {
+            let value = value.filter(|value| value != current)?;
+            let result = if relative { value - *current } else { value };
+            *current = value;
+            Some(result)
+        };
+        let params = GCodeParams {
+            x: axis(x, &mut self.x),
+            y: axis(y, &mut self.y),
+            z: axis(z, &mut self.z),
+            ..Default::default()
+        };
+        if params.have_axis() {
+            self.push(if linear {
+                GCodeCommand::Linear(params)
+            } else {
+                GCodeCommand::Rapid(params)
+            });
+        }
+    }
+
+    /// 快速移动
+    fn rapid(&mut self, x: Option<f64>, y: Option<f64>, z: Option<f64>) {
+        self.move_to(false, x, y, z);
+    }
+
+    /// Z轴进给
+    fn feed(&mut self, z: f64) {
+        self.move_to(true, None, None, Some(z));
+    }
+
+    /// 钻一个孔, 最后退回到[GCodeCycleHole::clear_z]
+    fn drill(&mut self, cycle: GCodeCycle, hole: &GCodeCycleHole) {
+        if cycle == GCodeCycle::BackBore {
+            self.back_bore(hole);
+            return;
+        }
+        self.rapid(Some(hole.x), Some(hole.y), None);
+        self.rapid(None, None, Some(hole.r));
+        match cycle {
+            GCodeCycle::ChipBreak => self.peck(true, hole),
+            GCodeCycle::Peck => self.peck(false, hole),
+            _ => self.feed(hole.z),
+        }
+        let dwell = matches!(
+            cycle,
+            GCodeCycle::DrillDwell
+                | GCodeCycle::BoreSpindleStop
+                | GCodeCycle::BoreManual
+                | GCodeCycle::BoreDwell
+        );
+        if let Some(p) = hole.p.filter(|_| dwell) {
+            self.push(GCodeCommand::Dwell(GCodeParams {
+                p: Some(p),
+                ..Default::default()
+            }));
+        }
+        match cycle {
+            //攻丝的主轴反转不会输出
+            GCodeCycle::Tap | GCodeCycle::Bore | GCodeCycle::BoreDwell => self.feed(hole.r),
+            //手动退出使用快速移动代替
+            GCodeCycle::BoreSpindleStop | GCodeCycle::BoreManual => {
+                self.push(GCodeCommand::SpindleOff)
+            }
+            _ => {}
+        }
+        self.rapid(None, None, Some(hole.clear_z));
+        if matches!(cycle, GCodeCycle::BoreSpindleStop | GCodeCycle::BoreManual) {
+            self.restart_spindle(hole);
+        }
+    }
+
+    /// 啄钻, 每次进给[GCodeCycleHole::q]的深度
+    /// - [chip_break] 为true时只回退[GCodeCycleHole::clearance]断屑, 否则退回到`R`再快速下降到上一次的孔底
+    fn peck(&mut self, chip_break: bool, hole: &GCodeCycleHole) {
+        let Some(q) = hole.q.filter(|q| *q > 0.0) else {
+            self.feed(hole.z);
+            return;
+        };
+        let mut depth = hole.r;
+        while depth > hole.z {
+            if depth < hole.r {
+                if !chip_break {
+                    self.rapid(None, None, Some(hole.r));
+                }
+                self.rapid(None, None, Some((depth + hole.clearance).min(hole.r)));
+            }
+            depth = (depth - q).max(hole.z);
+            self.feed(depth);
+        }
+    }
+
+    /// `G87` 背镗
+    /// - 主轴停止后从让刀的位置下降到孔底, 回到孔的中心后向上进给到`K`
+    fn back_bore(&mut self, hole: &GCodeCycleHole) {
+        let (x, y) = (hole.x + hole.i, hole.y + hole.j);
+        self.rapid(Some(x), Some(y), None);
+        self.push(GCodeCommand::SpindleOff);
+        self.rapid(None, None, Some(hole.z));
+        self.rapid(Some(hole.x), Some(hole.y), None);
+        self.restart_spindle(hole);
+        self.feed(hole.k);
+        self.feed(hole.z);
+        self.push(GCodeCommand::SpindleOff);
+        self.rapid(Some(x), Some(y), None);
+        self.rapid(None, None, Some(hole.clear_z));
+        self.rapid(Some(hole.x), Some(hole.y), None);
+        self.restart_spindle(hole);
+    }
+
+    /// 重新启动主轴
+    fn restart_spindle(&mut self, hole: &GCodeCycleHole) {
+        if let Some(spindle) = &hole.spindle {
+            self.push(spindle.clone());
+        }
+    }
+}
+
+/// 将固定循环展开成明确的运动, 输出GCode文本
+/// - 注释会保留
+pub struct GCodeValueHandlerCycle {
+    /// 模态状态, 在行与行之间保持
+    pub modal: GCodeModalState,
+    /// 输出
+    pub writer: GCodeWriter,
+    /// 保留几位小数点
+    pub digit: usize,
+}
+
+impl Default for GCodeValueHandlerCycle {
+    fn default() -> Self {
+        GCodeValueHandlerCycle {
+            modal: GCodeModalState::default(),
+            writer: GCodeWriter::default(),
+            digit: 6,
+        }
+    }
+}
+
+impl GCodeValueHandler for GCodeValueHandlerCycle {
+    fn handle_comment(&mut self, comment: &GCodeComment) {
+        if comment.is_paren {
+            self.writer.write_line(&format!("({})", comment.text));
+        } else {
+            self.writer.write_line(&format!(";{}", comment.text));
+        }
+    }
+
+    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+        let commands = GCodeCommand::from_values(&gcode_value_line);
+        for commands in self.modal.expand_cycle(&commands) {
+            let line = commands
+                .iter()
+                .map(|command| command.to_gcode(self.digit))
+                .collect::<Vec<String>>()
+                .join(" ");
+            self.writer.write_line(&line);
+            self.modal.apply(&commands);
+        }
+        GCodeFlow::Continue
+    }
+}
+
+/// 将GCode中的固定循环展开成明确的`G0`/`G1`/`G4`运动
+/// - [digit] 保留几位小数点
+pub fn expand_cycles(gcode: &String, digit: usize) -> String {
+    let mut handler = GCodeValueHandlerCycle {
+        digit,
+        ..Default::default()
+    };
+    GCodeParser::new(gcode).parse(&mut handler);
+    handler.writer.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::{GCodeCycle, GCodeParams};
+    use crate::cycle::expand_cycles;
+    use crate::handler::GCodeValueHandlerPath;
+    use crate::parser::GCodeParser;
+    use crate::writer::GCodeWriter;
+
+    #[test]
+    fn test_gcode_cycle_expand() {
+        let gcode = "G0 Z5\nG98 G81 X10 Y10 Z-2 R1 F100\nX20\nG80\nG0 X0 Y0".to_string();
+        assert_eq!(
+            expand_cycles(&gcode, 3),
+            "G0 Z5\nG98 F100\nG0 X10 Y10\nG0 Z1\nG1 Z-2\nG0 Z5\nG0 X20\nG0 Z1\nG1 Z-2\nG0 Z5\nG80\nG0 X0 Y0"
+        );
+
+        //啄钻, 退回到`R`
+        let gcode = "G0 Z1\nG99 G83 X0 Y0 Z-3 R1 Q2 F100".to_string();
+        assert_eq!(
+            expand_cycles(&gcode, 3),
+            "G0 Z1\nG99 F100\nG1 Z-1\nG0 Z1\nG0 Z-0.746\nG1 Z-3\nG0 Z1"
+        );
+
+        //断屑, 相对坐标重复
+        let gcode = "G91 G0 Z1\nG73 X5 Z-3 R-1 Q2 L2 F100\nG82 P0.5 Z-1".to_string();
+        assert_eq!(
+            expand_cycles(&gcode, 3),
+            "G91 G0 Z1\nF100\nG0 X5\nG0 Z-1\nG1 Z-2\nG0 Z0.254\nG1 Z-1.254\nG0 Z4\nG0 X5\nG0 Z-1\nG1 Z-2\nG0 Z0.254\nG1 Z-1.254\nG0 Z4\nG0 Z-1\nG1 Z-1\nG4 P0.5\nG0 Z2"
+        );
+    }
+
+    #[test]
+    fn test_gcode_cycle_path() {
+        let gcode = "G0 Z5\nM3 S1000\nG81 X10 Y10 Z-2 R1 F100\nX20\nY20\nG80\nG0 X0 Y0".to_string();
+        let mut handler = GCodeValueHandlerPath::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_eq!(handler.layers.len(), 1);
+        let holes: Vec<(f64, f64, f64)> = handler.layers[0]
+            .segments
+            .iter()
+            .map(|s| (s.to.x, s.to.y, s.to.z))
+            .collect();
+        assert_eq!(
+            holes,
+            vec![(10.0, 10.0, -2.0), (20.0, 10.0, -2.0), (20.0, 20.0, -2.0)]
+        );
+        assert!(handler.layers[0].segments.iter().all(|s| s.cycle.is_some()));
+    }
+
+    #[test]
+    fn test_gcode_cycle_writer() {
+        let mut writer = GCodeWriter::new(3);
+        writer.move_to(0.0, 0.0);
+        let params = GCodeParams {
+            z: Some(-2.0),
+            r: Some(1.0),
+            q: Some(1.5),
+            f: Some(100.0),
+            ..Default::default()
+        };
+        writer.drill_to(GCodeCycle::Peck, 10.0, 10.0, &params);
+        writer.drill_to(GCodeCycle::Peck, 20.0, 10.0, &params);
+        writer.move_to(0.0, 0.0);
+        let gcode = writer.to_string();
+        assert_eq!(
+            gcode,
+            "G0 X0 Y0\nG83 X10 Y10 Z-2 R1 Q1.5 F100\nX20 Y10\nG80\nG0 X0 Y0"
+        );
+
+        let mut writer = GCodeWriter::new(3);
+        writer.drill_to(GCodeCycle::Drill, 1.0, 2.0, &GCodeParams::default());
+        assert_eq!(writer.to_string(), "G81 X1 Y2");
+
+        let mut handler = GCodeValueHandlerPath::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_eq!(handler.layers[0].segments.len(), 4);
+    }
+}
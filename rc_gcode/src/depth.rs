@@ -0,0 +1,224 @@
+use crate::writer::{GCodeOutput, GCodeWriter};
+
+///
+/// @author <a href="mailto: angcyo@126.com">angcyo</a>
+/// @date 2026/10/18
+///
+/// 多次切割, 每一次Z下降一层, 用于CNC铣削或者厚材料的激光切割(焦点下移)
+/// - 单位和坐标相同, 通常为mm
+#[derive(Clone, Debug, PartialEq)]
+pub struct GCodeDepthOptions {
+    /// 材料表面的Z
+    pub top_z: f64,
+    /// 总深度, 从[top_z]向下
+    pub depth: f64,
+    /// 每一次下降的深度
+    pub step_down: f64,
+    /// 安全高度, 轮廓之间空走时抬刀到这个Z
+    pub safe_z: f64,
+    /// 切割的进给速度`F`
+    pub feed_rate: f64,
+    /// 下刀的进给速度`F`, 斜线下刀也使用这个速度
+    pub plunge_feed_rate: f64,
+    /// 斜线下刀的长度, 沿着轮廓边走边下降, 0表示垂直下刀
+    /// - 只对闭合的轮廓有效, 不闭合的轮廓垂直下刀
+    pub ramp_length: f64,
+}
+
+impl Default for GCodeDepthOptions {
+    fn default() -> Self {
+        GCodeDepthOptions {
+            top_z: 0.0,
+            depth: 1.0,
+            step_down: 1.0,
+            safe_z: 5.0,
+            feed_rate: 1000.0,
+            plunge_feed_rate: 300.0,
+            ramp_length: 0.0,
+        }
+    }
+}
+
+impl GCodeDepthOptions {
+    /// 每一次切割的Z, 最后一次为`top_z - depth`
+    pub fn pass_z(&self) -> Vec<f64> {
+        if self.depth <= 0.0 || self.step_down <= 0.0 {
+            return vec![self.top_z];
+        }
+        let count = (self.depth / self.step_down - 1e-9).ceil().max(1.0) as usize;
+        (1..=count)
+            .map(|i| self.top_z - (self.step_down * i as f64).min(self.depth))
+            .collect()
+    }
+
+    /// 多次切割一个轮廓, 开始和结束时都在[safe_z]
+    /// - 先快速下降到[top_z], 只有切入材料的部分使用[plunge_feed_rate]
+    /// - [points] 轮廓的点, 第一个点为起点
+    /// - [closed] 是否闭合, 闭合的轮廓最后一个点和起点相同
+    pub fn write_contour<O: GCodeOutput>(
+        &self,
+        writer: &mut GCodeWriter<O>,
+        points: &[(f64, f64)],
+        closed: bool,
+    ) {
+        let Some(&(x, y)) = points.first() else {
+            return;
+        };
+        writer.set_feed_rate(None);
+        writer.move_to(x, y);
+        //快速下降到材料表面, 之后只用下刀速度切入材料
+        writer.z_to(self.top_z, true);
+        let ramp = closed && self.ramp_length > 0.0 && points.len() > 1;
+        let pass_z = self.pass_z();
+        let mut last_z = self.top_z;
+        for (index, &z) in pass_z.iter().enumerate() {
+            if ramp {
+                //闭合的轮廓, 每一次都从起点开始, 上一次结束的位置
+                let (ramp_end, ramp_point) = self.write_ramp(writer, points, last_z, z);
+                writer.set_feed_rate(Some(self.feed_rate));
+                for &(x, y) in &points[ramp_end..] {
+                    writer.line_to(x, y);
+                }
+                if index == pass_z.len() - 1 {
+                    //最后一次补上斜线下刀的部分
+                    for &(x, y) in &points[1..ramp_end] {
+                        writer.line_to(x, y);
+                    }
+                    if points[ramp_end - 1] != ramp_point {
+                        writer.line_to(ramp_point.0, ramp_point.1);
+                    }
+                }
+            } else {
+                if index > 0 && !closed {
+                    //不闭合的轮廓, 回到起点, 上一层已经切开, 可以快速下降
+                    writer.z_to(self.safe_z, true);
+                    writer.set_feed_rate(None);
+                    writer.move_to(x, y);
+                    writer.z_to(last_z, true);
+                }
+                writer.set_feed_rate(Some(self.plunge_feed_rate));
+                writer.z_to(z, false);
+                writer.set_feed_rate(Some(self.feed_rate));
+                for &(x, y) in &points[1..] {
+                    writer.line_to(x, y);
+                }
+            }
+            last_z = z;
+        }
+        writer.z_to(self.safe_z, true);
+    }
+
+    /// 从起点沿着轮廓斜线下刀, 从[from_z]下降到[to_z]
+    /// - 轮廓的长度不够时, 在轮廓的终点到达[to_z]
+    /// - 返回之后需要继续切割的第一个点的下标, 和斜线结束的位置
+    fn write_ramp<O: GCodeOutput>(
+        &self,
+        writer: &mut GCodeWriter<O>,
+        points: &[(f64, f64)],
+        from_z: f64,
+        to_z: f64,
+    ) -> (usize, (f64, f64)) {
+        let length: f64 = points
+            .windows(2)
+            .map(|pair| (pair[1].0 - pair[0].0).hypot(pair[1].1 - pair[0].1))
+            .sum();
+        let ramp_length = self.ramp_length.min(length);
+        writer.set_feed_rate(Some(self.plunge_feed_rate));
+        let mut distance = 0.0;
+        for (index, pair) in points.windows(2).enumerate() {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            let segment = (x1 - x0).hypot(y1 - y0);
+            if distance + segment >= ramp_length {
+                //斜线在这一段结束
+                let t = if segment > 0.0 {
+                    (ramp_length - distance) / segment
+                } else {
+                    1.0
+                };
+                if t >= 1.0 {
+                    writer.line_to_z(x1, y1, to_z);
+                    return (index + 2, (x1, y1));
+                }
+                let (x, y) = (x0 + (x1 - x0) * t, y0 + (y1 - y0) * t);
+                writer.line_to_z(x, y, to_z);
+                return (index + 1, (x, y));
+            }
+            distance += segment;
+            let z = from_z + (to_z - from_z) * distance / ramp_length;
+            writer.line_to_z(x1, y1, z);
+        }
+        (points.len(), points[points.len() - 1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::depth::GCodeDepthOptions;
+    use crate::parser::GCodeParser;
+    use crate::path_to_gcode_passes;
+    use crate::stats::GCodeValueHandlerStatistics;
+    use lyon_path::Path;
+    use lyon_path::math::point;
+
+    /// 10x10的正方形
+    fn square(close: bool) -> Path {
+        let mut builder = Path::builder();
+        builder.begin(point(0.0, 0.0));
+        builder.line_to(point(10.0, 0.0));
+        builder.line_to(point(10.0, 10.0));
+        builder.line_to(point(0.0, 10.0));
+        builder.end(close);
+        builder.build()
+    }
+
+    #[test]
+    fn test_depth_pass_z() {
+        let options = GCodeDepthOptions {
+            depth: 2.5,
+            step_down: 1.0,
+            ..Default::default()
+        };
+        assert_eq!(options.pass_z(), vec![-1.0, -2.0, -2.5]);
+        let options = GCodeDepthOptions {
+            top_z: 1.0,
+            depth: 2.0,
+            step_down: 1.0,
+            ..Default::default()
+        };
+        assert_eq!(options.pass_z(), vec![0.0, -1.0]);
+    }
+
+    #[test]
+    fn test_path_to_gcode_passes() {
+        let options = GCodeDepthOptions {
+            depth: 2.0,
+            step_down: 1.0,
+            ..Default::default()
+        };
+        let gcode = path_to_gcode_passes(&square(true), 0.01, 3, "G90 G21", &options);
+        assert_eq!(
+            gcode,
+            "G90 G21\nG0 Z5\nG0 X0 Y0\nG0 Z0\nG1 Z-1 F300\nG1 X10 Y0 F1000\nG1 X10 Y10 F1000\nG1 X0 Y10 F1000\nG1 X0 Y0 F1000\nG1 Z-2 F300\nG1 X10 Y0 F1000\nG1 X10 Y10 F1000\nG1 X0 Y10 F1000\nG1 X0 Y0 F1000\nG0 Z5"
+        );
+
+        //不闭合的轮廓, 每一次回到起点
+        let gcode = path_to_gcode_passes(&square(false), 0.01, 3, "", &options);
+        assert!(gcode.contains("G1 X0 Y10 F1000\nG0 Z5\nG0 X0 Y0\nG0 Z-1\nG1 Z-2 F300\n"));
+
+        //斜线下刀
+        let options = GCodeDepthOptions {
+            ramp_length: 15.0,
+            ..options
+        };
+        let gcode = path_to_gcode_passes(&square(true), 0.01, 3, "", &options);
+        assert_eq!(
+            gcode,
+            "G0 Z5\nG0 X0 Y0\nG0 Z0\nG1 X10 Y0 Z-0.667 F300\nG1 X10 Y5 Z-1 F300\nG1 X10 Y10 F1000\nG1 X0 Y10 F1000\nG1 X0 Y0 F1000\nG1 X10 Y0 Z-1.667 F300\nG1 X10 Y5 Z-2 F300\nG1 X10 Y10 F1000\nG1 X0 Y10 F1000\nG1 X0 Y0 F1000\nG1 X10 Y0 F1000\nG1 X10 Y5 F1000\nG0 Z5"
+        );
+        let mut handler = GCodeValueHandlerStatistics::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let statistics = &handler.statistics;
+        //快速下降到材料表面, 每一次斜线下刀和轮廓40 + 补上斜线下刀的部分15, 斜线略长于平面的长度
+        assert!(statistics.cut_length > 95.0 && statistics.cut_length < 95.1);
+    }
+}
@@ -0,0 +1,381 @@
+use crate::checksum::gcode_checksum;
+use crate::parser::{GCodeLine, GCodeSpan, GCodeValue};
+
+///
+/// @author <a href="mailto: angcyo@126.com">angcyo</a>
+/// @date 2026/10/18
+///
+/// GCode 校验诊断信息
+#[derive(Clone, Debug, PartialEq)]
+pub struct GCodeDiagnostic {
+    /// 诊断类型
+    pub kind: GCodeDiagnosticKind,
+    /// 描述信息
+    pub message: String,
+    /// 出错的位置
+    pub span: GCodeSpan,
+}
+
+/// 诊断类型
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GCodeDiagnosticKind {
+    /// 数值格式错误, 比如`X1.2.3` / `Y--5` / 缺少数值的`X`
+    MalformedNumber,
+    /// 同一行中重复的参数, 比如`G1 X1 X2`
+    DuplicateWord,
+    /// 未知的指令, 比如`G7` / `#1`
+    UnknownCommand,
+    /// 数值超出范围, 比如负数的`F`
+    OutOfRange,
+    /// `*`校验和不匹配
+    ChecksumMismatch,
+    /// `N`行号不连续
+    LineNumberMismatch,
+}
+
+impl GCodeDiagnostic {
+    pub fn new(kind: GCodeDiagnosticKind, message: String, span: GCodeSpan) -> Self {
+        Self {
+            kind,
+            message,
+            span,
+        }
+    }
+}
+
+/// 实现[Display]
+impl std::fmt::Display for GCodeDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.span.line, self.message)
+    }
+}
+
+impl std::error::Error for GCodeDiagnostic {}
+
+/// 流式解析出错
+#[derive(Debug)]
+pub enum GCodeParseError {
+    /// 读取数据源出错
+    Io(std::io::Error),
+    /// 数据校验未通过
+    Invalid(Vec<GCodeDiagnostic>),
+}
+
+impl From<std::io::Error> for GCodeParseError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// 实现[Display]
+impl std::fmt::Display for GCodeParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{}", error),
+            Self::Invalid(diagnostics) => {
+                let lines: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+        }
+    }
+}
+
+impl std::error::Error for GCodeParseError {}
+
+/// 支持的`G`指令
+const KNOWN_G_CODES: &[&str] = &[
+    "0", "1", "2", "3", "4", "5", "5.1", "5.2", "10", "17", "18", "19", "20", "21", "28", "28.1",
+    "29", "30", "30.1", "38.2", "38.3", "38.4", "38.5", "40", "41", "42", "43", "43.1", "49", "53",
+    "54", "55", "56", "57", "58", "59", "59.1", "59.2", "59.3", "61", "64", "73", "76", "80", "81",
+    "82", "83", "84", "85", "86", "87", "88", "89", "90", "90.1", "91", "91.1", "92", "92.1",
+    "92.2", "92.3", "93", "94", "95", "96", "97", "98", "99",
+];
+
+/// 可以只有字母不需要数值的指令, 比如`G28 X Y`回零指定的轴
+const AXIS_FLAG_CODES: &[&str] = &["G28", "G29", "M17", "M18", "M84"];
+
+/// 坐标和圆弧参数的字母, 只有这些会检查[GCodeValidator::max_axis_value]
+/// - `P`(暂停时间/程序号)/`F`/`S`等不是坐标, 可以很大
+const AXIS_WORDS: &[&str] = &[
+    "X", "Y", "Z", "A", "B", "C", "U", "V", "W", "I", "J", "K", "R",
+];
+
+/// GCode 数据校验
+#[derive(Clone, Debug)]
+pub struct GCodeValidator {
+    /// 严格模式, 遇到第一个错误就中断解析
+    pub strict: bool,
+    /// 坐标数值允许的最大绝对值, 只检查[AXIS_WORDS]
+    pub max_axis_value: f64,
+    /// 是否校验`*`校验和, 只校验有校验和的行
+    pub check_checksum: bool,
+    /// 是否校验`N`行号连续, `M110`可以重置行号
+    /// - 用于Marlin/RepRap串口协议, CNC程序中的行号通常不连续
+    pub check_line_number: bool,
+    /// 上一行的行号
+    last_line_number: Option<i64>,
+}
+
+impl Default for GCodeValidator {
+    fn default() -> Self {
+        Self {
+            strict: false,
+            max_axis_value: 100_000.0,
+            check_checksum: true,
+            check_line_number: false,
+            last_line_number: None,
+        }
+    }
+}
+
+impl GCodeValidator {
+    /// 校验一行数据, 返回所有的诊断信息
+    pub fn validate(&mut self, line: &GCodeLine) -> Vec<GCodeDiagnostic> {
+        let mut diagnostics = self._validate_values(line);
+        if self.check_checksum {
+            diagnostics.extend(self._validate_checksum(line));
+        }
+        if self.check_line_number {
+            diagnostics.extend(self._validate_line_number(line));
+        }
+        diagnostics
+    }
+
+    /// 校验`*`校验和
+    fn _validate_checksum(&self, line: &GCodeLine) -> Option<GCodeDiagnostic> {
+        let checksum = line.values.iter().find(|v| v.is_checksum())?;
+        let end = checksum.span.offset - line.span.offset;
+        let expected = gcode_checksum(line.text.get(..end)?);
+        match checksum.value.parse::<u8>() {
+            Ok(value) if value == expected => None,
+            _ => Some(GCodeDiagnostic::new(
+                GCodeDiagnosticKind::ChecksumMismatch,
+                format!(
+                    "checksum mismatch `{}`, expected `*{}`",
+                    checksum.to_string(),
+                    expected
+                ),
+                checksum.span,
+            )),
+        }
+    }
+
+    /// 校验`N`行号
+    fn _validate_line_number(&mut self, line: &GCodeLine) -> Option<GCodeDiagnostic> {
+        let values = &line.values;
+        let number = values.iter().find(|v| v.command == "N")?;
+        let line_number = number.value_f64() as i64;
+        let is_reset = values
+            .iter()
+            .any(|v| v.command == "M" && v.value_f64() == 110.0);
+        if is_reset {
+            //`M110 N100`, 使用最后一个`N`作为新的行号
+            let reset = values.iter().rfind(|v| v.command == "N")?;
+            self.last_line_number = Some(reset.value_f64() as i64);
+            return None;
+        }
+        let expected = self.last_line_number.map(|n| n + 1);
+        self.last_line_number = Some(line_number);
+        match expected {
+            Some(expected) if expected != line_number => Some(GCodeDiagnostic::new(
+                GCodeDiagnosticKind::LineNumberMismatch,
+                format!(
+                    "line number `{}`, expected `N{}`",
+                    number.to_string(),
+                    expected
+                ),
+                number.span,
+            )),
+            _ => None,
+        }
+    }
+
+    /// 校验每个[GCodeValue]
+    fn _validate_values(&self, line: &GCodeLine) -> Vec<GCodeDiagnostic> {
+        let mut diagnostics = vec![];
+        let allow_flags = line
+            .values
+            .iter()
+            .any(|v| AXIS_FLAG_CODES.iter().any(|code| Self::_code_eq(v, code)));
+        let mut words: Vec<&str> = vec![];
+        for value in line.values.iter() {
+            if value.is_unknown() {
+                diagnostics.push(GCodeDiagnostic::new(
+                    GCodeDiagnosticKind::UnknownCommand,
+                    format!("unknown word `{}`", value.to_string()),
+                    value.span,
+                ));
+                continue;
+            }
+            if value.value.is_empty() && allow_flags {
+                continue;
+            }
+            let number = match value.try_value_f64() {
+                Ok(number) => number,
+                Err(diagnostic) => {
+                    diagnostics.push(diagnostic);
+                    continue;
+                }
+            };
+            match value.command.as_str() {
+                "G" => {
+                    if !KNOWN_G_CODES.contains(&Self::_trim_code(&value.value).as_str()) {
+                        diagnostics.push(GCodeDiagnostic::new(
+                            GCodeDiagnosticKind::UnknownCommand,
+                            format!("unknown command `{}`", value.to_string()),
+                            value.span,
+                        ));
+                    }
+                }
+                "M" | "T" | "N" | "*" => {
+                    if number < 0.0 || number.fract() != 0.0 {
+                        diagnostics.push(GCodeDiagnostic::new(
+                            GCodeDiagnosticKind::OutOfRange,
+                            format!("`{}` must be a positive integer", value.to_string()),
+                            value.span,
+                        ));
+                    }
+                }
+                command => {
+                    if words.contains(&command) {
+                        diagnostics.push(GCodeDiagnostic::new(
+                            GCodeDiagnosticKind::DuplicateWord,
+                            format!("duplicate word `{}`", command),
+                            value.span,
+                        ));
+                    }
+                    words.push(command);
+
+                    if (command == "F" || command == "S") && number < 0.0 {
+                        diagnostics.push(GCodeDiagnostic::new(
+                            GCodeDiagnosticKind::OutOfRange,
+                            format!("`{}` must not be negative", value.to_string()),
+                            value.span,
+                        ));
+                    } else if AXIS_WORDS.contains(&command) && number.abs() > self.max_axis_value {
+                        diagnostics.push(GCodeDiagnostic::new(
+                            GCodeDiagnosticKind::OutOfRange,
+                            format!(
+                                "`{}` exceeds the limit {}",
+                                value.to_string(),
+                                self.max_axis_value
+                            ),
+                            value.span,
+                        ));
+                    }
+                }
+            }
+        }
+        diagnostics
+    }
+
+    /// 去掉指令数值前面的0, `01` -> `1`
+    fn _trim_code(value: &str) -> String {
+        let trimmed = value.trim_start_matches('0');
+        if trimmed.is_empty() || trimmed.starts_with('.') {
+            format!("0{}", trimmed)
+        } else {
+            trimmed.to_string()
+        }
+    }
+
+    /// 指令是否相等, 忽略数值前面的0
+    fn _code_eq(value: &GCodeValue, code: &str) -> bool {
+        code.starts_with(value.command.as_str())
+            && Self::_trim_code(&value.value) == code[value.command.len()..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic::{GCodeDiagnosticKind, GCodeValidator};
+    use crate::handler::{GCodeFlow, GCodeValueHandler};
+    use crate::parser::{GCodeParser, GCodeValue};
+
+    #[derive(Default)]
+    struct CountHandler {
+        count: usize,
+        is_end: bool,
+    }
+
+    impl GCodeValueHandler for CountHandler {
+        fn handle_gcode_value(&mut self, _gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+            self.count += 1;
+            GCodeFlow::Continue
+        }
+
+        fn end(&mut self) {
+            self.is_end = true;
+        }
+    }
+
+    #[test]
+    fn test_gcode_try_parse() {
+        let gcode =
+            "G90\nG1 X1.2.3 Y--5\nG1 X1 X2 F-10\nG7 #1\nG28 X Y\nG01 X1e5\nG1 X200000".to_string();
+        let mut handler = CountHandler::default();
+        let diagnostics = GCodeParser::new(&gcode)
+            .try_parse(&mut handler)
+            .unwrap_err();
+        let kinds: Vec<(usize, GCodeDiagnosticKind)> =
+            diagnostics.iter().map(|d| (d.span.line, d.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (2, GCodeDiagnosticKind::MalformedNumber),
+                (2, GCodeDiagnosticKind::MalformedNumber),
+                (3, GCodeDiagnosticKind::DuplicateWord),
+                (3, GCodeDiagnosticKind::OutOfRange),
+                (4, GCodeDiagnosticKind::UnknownCommand),
+                (4, GCodeDiagnosticKind::UnknownCommand),
+                (7, GCodeDiagnosticKind::OutOfRange),
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "line 2: malformed number `X1.2.3`"
+        );
+        //只有合法的行才会交给处理器
+        assert_eq!(handler.count, 3);
+
+        let mut handler = CountHandler::default();
+        let mut parser = GCodeParser::new(&gcode);
+        parser.validator = GCodeValidator {
+            strict: true,
+            ..Default::default()
+        };
+        let diagnostics = parser.try_parse(&mut handler).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(handler.count, 1);
+        //中断解析也会结束处理器
+        assert!(handler.is_end);
+
+        let gcode = "G90\nG1 X1 Y2".to_string();
+        assert!(GCodeParser::new(&gcode).try_parse(&mut handler).is_ok());
+
+        //只检查坐标, 暂停时间`P`不受限制
+        let gcode = "G4 P150000\nG1 X10 F200000 S150000".to_string();
+        let mut parser = GCodeParser::new(&gcode);
+        parser.validator.strict = true;
+        assert!(parser.try_parse(&mut handler).is_ok());
+    }
+
+    #[test]
+    fn test_gcode_checksum_validate() {
+        let gcode = "N0 M110 N0*125\nN1 G1 X1*96\nN2 G1 X2*00\nN4 G1 X3*103".to_string();
+        let mut handler = CountHandler::default();
+        let mut parser = GCodeParser::new(&gcode);
+        parser.validator.check_line_number = true;
+        let diagnostics = parser.try_parse(&mut handler).unwrap_err();
+        let kinds: Vec<(usize, GCodeDiagnosticKind)> =
+            diagnostics.iter().map(|d| (d.span.line, d.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (3, GCodeDiagnosticKind::ChecksumMismatch),
+                (4, GCodeDiagnosticKind::LineNumberMismatch),
+            ]
+        );
+        assert_eq!(handler.count, 2);
+    }
+}
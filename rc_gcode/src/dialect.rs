@@ -0,0 +1,239 @@
+use crate::writer::format_number;
+
+///
+/// @author <a href="mailto: angcyo@126.com">angcyo</a>
+/// @date 2026/10/18
+///
+/// 激光的开关方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GCodeLaserControl {
+    /// `M3 S`恒定功率, `M5`关闭
+    #[default]
+    Constant,
+    /// `M4 S`动态功率, 功率跟随速度变化, `M5`关闭, GRBL激光模式`$32=1`
+    Dynamic,
+    /// `M106 S`风扇口控制激光, `M107`关闭, Marlin
+    Fan,
+    /// `M3 I`内联激光模式, 功率跟随每一次运动的`S`, `M5 I`关闭, Marlin
+    Inline,
+    /// 没有开关指令, `G1`运动时按照`S`出光, `G0`不出光, Smoothieware
+    Moves,
+}
+
+/// 固件方言, 控制[crate::writer::GCodeWriter]的输出
+/// - 激光的开关指令 / 功率范围 / 是否支持圆弧 / 开头和结尾的指令
+#[derive(Clone, Debug, PartialEq)]
+pub struct GCodeDialect {
+    /// 名称
+    pub name: String,
+    /// 开头的指令, 比如单位/坐标模式
+    pub header: Vec<String>,
+    /// 结尾的指令, 比如关闭激光/程序结束
+    pub footer: Vec<String>,
+    /// 激光的开关方式
+    pub laser_control: GCodeLaserControl,
+    /// 最大功率`S`, 功率`0~1`会缩放到`0~max_power`
+    pub max_power: f64,
+    /// 功率`S`保留几位小数点
+    pub power_digit: usize,
+    /// 是否在每一次切割运动上输出功率`S`
+    pub power_on_moves: bool,
+    /// 是否支持`G2`/`G3`圆弧, 不支持时圆弧会展平成`G1`
+    pub arc_support: bool,
+    /// 圆弧展平的公差
+    pub arc_tolerance: f64,
+}
+
+impl Default for GCodeDialect {
+    fn default() -> Self {
+        GCodeDialect {
+            name: "Generic".to_string(),
+            header: vec!["G90".to_string(), "G21".to_string()],
+            footer: vec!["M5".to_string(), "M2".to_string()],
+            laser_control: GCodeLaserControl::Constant,
+            max_power: 1000.0,
+            power_digit: 0,
+            power_on_moves: false,
+            arc_support: true,
+            arc_tolerance: 0.01,
+        }
+    }
+}
+
+impl GCodeDialect {
+    /// GRBL激光模式, `M4`动态功率, 运动上输出`S`
+    pub fn grbl() -> Self {
+        GCodeDialect {
+            name: "GRBL".to_string(),
+            header: vec!["$32=1".to_string(), "G90".to_string(), "G21".to_string()],
+            laser_control: GCodeLaserControl::Dynamic,
+            power_on_moves: true,
+            ..Default::default()
+        }
+    }
+
+    /// Marlin, 使用风扇口`M106`/`M107`控制激光
+    pub fn marlin() -> Self {
+        GCodeDialect {
+            name: "Marlin".to_string(),
+            footer: vec!["M107".to_string(), "M84".to_string()],
+            laser_control: GCodeLaserControl::Fan,
+            max_power: 255.0,
+            ..Default::default()
+        }
+    }
+
+    /// Marlin激光模式, `M3 I`内联功率
+    pub fn marlin_inline() -> Self {
+        GCodeDialect {
+            name: "Marlin Inline".to_string(),
+            footer: vec!["M5 I".to_string(), "M84".to_string()],
+            laser_control: GCodeLaserControl::Inline,
+            max_power: 255.0,
+            power_on_moves: true,
+            ..Default::default()
+        }
+    }
+
+    /// Smoothieware, 功率`S0.0~1.0`, `G1`运动时出光
+    pub fn smoothieware() -> Self {
+        GCodeDialect {
+            name: "Smoothieware".to_string(),
+            footer: vec!["M2".to_string()],
+            laser_control: GCodeLaserControl::Moves,
+            max_power: 1.0,
+            power_digit: 3,
+            power_on_moves: true,
+            ..Default::default()
+        }
+    }
+
+    /// Ruida类的上位机, 不支持圆弧, 功率为百分比
+    pub fn ruida() -> Self {
+        GCodeDialect {
+            name: "Ruida".to_string(),
+            max_power: 100.0,
+            power_digit: 1,
+            arc_support: false,
+            ..Default::default()
+        }
+    }
+
+    /// 将`0~1`的功率缩放到固件的功率范围
+    pub fn scale_power(&self, power: f64) -> f64 {
+        let scale = 10f64.powi(self.power_digit as i32);
+        (power.clamp(0.0, 1.0) * self.max_power * scale).round() / scale
+    }
+
+    /// 打开激光的指令, [None]表示不需要指令
+    /// - [power] 功率`0~1`
+    pub fn laser_on(&self, power: f64) -> Option<String> {
+        let power = format_number(self.scale_power(power), self.power_digit);
+        match self.laser_control {
+            GCodeLaserControl::Constant => Some(format!("M3 S{}", power)),
+            GCodeLaserControl::Dynamic => Some(format!("M4 S{}", power)),
+            GCodeLaserControl::Fan => Some(format!("M106 S{}", power)),
+            GCodeLaserControl::Inline => Some(format!("M3 I S{}", power)),
+            GCodeLaserControl::Moves => None,
+        }
+    }
+
+    /// 关闭激光的指令, [None]表示不需要指令
+    pub fn laser_off(&self) -> Option<&'static str> {
+        match self.laser_control {
+            GCodeLaserControl::Constant | GCodeLaserControl::Dynamic => Some("M5"),
+            GCodeLaserControl::Fan => Some("M107"),
+            GCodeLaserControl::Inline => Some("M5 I"),
+            GCodeLaserControl::Moves => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dialect::GCodeDialect;
+    use crate::parser::GCodeParser;
+    use crate::path_to_gcode_dialect;
+    use crate::stats::GCodeValueHandlerStatistics;
+    use crate::writer::GCodeWriter;
+    use lyon_path::math::point;
+    use lyon_path::{Path, Winding};
+
+    #[test]
+    fn test_gcode_dialect() {
+        let mut builder = Path::builder();
+        builder.begin(point(0.0, 0.0));
+        builder.line_to(point(10.0, 0.0));
+        builder.line_to(point(10.0, 10.0));
+        builder.end(true);
+        let path = builder.build();
+
+        let gcode = path_to_gcode_dialect(&path, 0.01, 3, &GCodeDialect::grbl(), 0.5, 1000.0);
+        assert_eq!(
+            gcode,
+            "$32=1\nG90\nG21\nG0 X0 Y0\nM4 S500\nG1 X10 F1000 S500\nY10\nX0 Y0\nM5\nM2"
+        );
+
+        let gcode = path_to_gcode_dialect(&path, 0.01, 3, &GCodeDialect::marlin(), 0.5, 1000.0);
+        assert_eq!(
+            gcode,
+            "G90\nG21\nG0 X0 Y0\nM106 S128\nG1 X10 F1000\nY10\nX0 Y0\nM107\nM84"
+        );
+
+        let gcode =
+            path_to_gcode_dialect(&path, 0.01, 3, &GCodeDialect::smoothieware(), 0.25, 1000.0);
+        assert_eq!(
+            gcode,
+            "G90\nG21\nG0 X0 Y0\nG1 X10 F1000 S0.25\nY10\nX0 Y0\nM2"
+        );
+
+        //只有起点的轮廓只移动, 不打开激光
+        let mut builder = Path::builder();
+        builder.begin(point(5.0, 5.0));
+        builder.end(false);
+        let path = builder.build();
+        let gcode = path_to_gcode_dialect(&path, 0.01, 3, &GCodeDialect::grbl(), 0.5, 1000.0);
+        assert_eq!(gcode, "$32=1\nG90\nG21\nG0 X5 Y5\nM2");
+    }
+
+    #[test]
+    fn test_gcode_dialect_arc() {
+        let mut builder = Path::builder();
+        builder.add_circle(point(0.0, 0.0), 20.0, Winding::Positive);
+        let path = builder.build();
+
+        let gcode = path_to_gcode_dialect(&path, 0.01, 4, &GCodeDialect::default(), 1.0, 600.0);
+        assert!(gcode.contains("G3"));
+        let gcode = path_to_gcode_dialect(&path, 0.01, 4, &GCodeDialect::ruida(), 1.0, 600.0);
+        assert!(
+            gcode
+                .lines()
+                .all(|line| !line.starts_with("G2 ") && !line.starts_with("G3 "))
+        );
+        assert!(gcode.contains("M3 S100\n"));
+
+        //激光还没有关闭时, 结尾关闭激光
+        let mut writer = GCodeWriter::with_dialect(3, GCodeDialect::default());
+        writer.laser_on(1.0);
+        writer.line_to(1.0, 0.0);
+        writer.end();
+        assert_eq!(writer.to_string(), "M3 S1000\nG1 X1 Y0\nM5\nM2");
+
+        //不支持圆弧时展平, 长度不变
+        let mut writer = GCodeWriter::with_dialect(6, GCodeDialect::ruida());
+        writer.move_to(10.0, 0.0);
+        writer.arc_to(-10.0, 0.0, 0.0, 0.0, false);
+        let gcode = writer.to_string();
+        assert!(!gcode.contains("G3"));
+        let mut handler = GCodeValueHandlerStatistics::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let length = handler.statistics.cut_length;
+        assert!(
+            (length - std::f64::consts::PI * 10.0).abs() < 0.01,
+            "{}",
+            length
+        );
+        let bounds = handler.statistics.bounds.unwrap();
+        assert!((bounds.max_y - 10.0).abs() < 0.01);
+    }
+}
@@ -1,6 +1,14 @@
-use crate::parser::GCodeValue;
-use lyon_path::geom::point;
+use crate::command::{GCodeCommand, GCodeCycle, GCodePlane};
+use crate::metadata::is_layer_comment;
+use crate::modal::{
+    ARC_FLATTEN_TOLERANCE, GCodeCoordinates, GCodeExtrusionState, GCodeModalState, GCodeMotion,
+    GCodeMotionMode, GCodePosition, GCodeSpline, filament_weight,
+};
+use crate::parser::{GCodeComment, GCodeLine, GCodeValue};
+use crate::writer::format_number;
 use lyon_path::Path;
+use lyon_path::geom::{Angle, Arc, point, vector};
+use lyon_path::math::Point;
 use std::cell::RefCell;
 
 ///
@@ -11,12 +19,57 @@ use std::cell::RefCell;
 pub trait GCodeValueHandler {
     /// 开始
     fn start(&mut self) {}
+    /// 处理注释, 会在所在行的[handle_gcode_line]之前回调
+    fn handle_comment(&mut self, _comment: &GCodeComment) {}
+    /// 处理带有源数据位置信息的一行数据
+    /// - 默认只转发[GCodeLine::values]到[handle_gcode_value]
+    fn handle_gcode_line(&mut self, gcode_line: GCodeLine) -> GCodeFlow {
+        if gcode_line.values.is_empty() {
+            GCodeFlow::Continue
+        } else {
+            self.handle_gcode_value(gcode_line.values)
+        }
+    }
     /// 处理[GCodeValue]
-    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>);
+    /// - 返回[GCodeFlow::Abort]时中断解析
+    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow;
     /// 结束
     fn end(&mut self) {}
 }
 
+/// 处理完一行数据之后, 是否继续解析
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GCodeFlow {
+    /// 继续解析
+    #[default]
+    Continue,
+    /// 中断解析, 之后的数据不会再回调, [GCodeValueHandler::end]仍然会回调
+    Abort,
+}
+
+/// 转发到引用的处理器, 方便组合时借用处理器, 解析之后再读取结果
+impl<H: GCodeValueHandler + ?Sized> GCodeValueHandler for &mut H {
+    fn start(&mut self) {
+        (**self).start()
+    }
+
+    fn handle_comment(&mut self, comment: &GCodeComment) {
+        (**self).handle_comment(comment)
+    }
+
+    fn handle_gcode_line(&mut self, gcode_line: GCodeLine) -> GCodeFlow {
+        (**self).handle_gcode_line(gcode_line)
+    }
+
+    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+        (**self).handle_gcode_value(gcode_value_line)
+    }
+
+    fn end(&mut self) {
+        (**self).end()
+    }
+}
+
 /// 日志输出实现
 pub struct GCodeValueHandlerImpl {
     /// 行数
@@ -34,9 +87,14 @@ impl GCodeValueHandler for GCodeValueHandlerImpl {
         self.line_count = 0;
     }
 
-    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) {
+    fn handle_comment(&mut self, comment: &GCodeComment) {
+        println!("comment:{}", comment.text);
+    }
+
+    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
         self.line_count += 1;
         println!("{:?}", gcode_value_line);
+        GCodeFlow::Continue
     }
 
     fn end(&mut self) {
@@ -45,68 +103,79 @@ impl GCodeValueHandler for GCodeValueHandlerImpl {
 }
 
 /// 将[GCodeValue]解析成[Path]
-/// - 如果遇到了Z, 那么之后的数据都会合并到一层中
+/// - 如果遇到了只有Z的移动, 那么之后的数据都会合并到一层中
+/// - 开启[layer_comments]时, 使用切片软件的层标记分层, 层中z-hop的Z会被忽略
+/// - 只有坐标的行, 使用[GCodeModalState]中的运动模式
+/// - 每一段直线/圆弧的属性记录在[GCodeValueHandlerPathLayer::segments]中
+/// - 路径使用[coordinates]坐标系输出, 工件坐标系的偏移在[GCodeModalState::work_offsets]中设置
+/// - 切割的运动在[GCodeValueHandlerPathLayer::path]中, 空走的运动在[GCodeValueHandlerPathLayer::travel_path]中
+/// - 固定循环会展开成明确的运动, 每一次孔底的进给在[GCodeValueHandlerPathLayer::path]中为一个点
 pub struct GCodeValueHandlerPath {
     /// 每一层的数据
     pub layers: Vec<GCodeValueHandlerPathLayer>,
+    /// 模态状态, 在行与行之间保持
+    pub modal: GCodeModalState,
+    /// 输出路径使用的坐标系
+    pub coordinates: GCodeCoordinates,
+    /// 激光模式, 只有激光打开并且功率>0时的运动才是切割
+    /// - 否则只有`G0`是空走
+    pub laser_mode: bool,
+    /// 激光模式下`G0`是否总是关闭激光, 比如GRBL
+    /// - 为false时, `M3`恒定功率模式下的`G0`也会切割, `M4`动态功率模式下的`G0`总是空走
+    pub rapid_laser_off: bool,
+    /// 是否使用`;LAYER:`/`;LAYER_CHANGE`注释分层, 而不是只有Z的移动
+    pub layer_comments: bool,
+    /// 挤出的耗材长度, 单位mm, 回抽会减去
+    pub filament_length: f64,
     //--
-    /// 当前数据相对于mm需要缩放的系数
-    /// 用来将英寸值转换成mm值
-    /// 1inch = 25.4mm
-    mm_value_scale: f32,
-    /// 当前是否是相对坐标
-    is_relative: bool,
-    /// 最后一次的x/y
-    last_x: f32,
-    last_y: f32,
     /// 当前数据所处的z坐标
     z: Option<GCodeValue>,
+    /// 当前层每一段的属性
+    segments: Vec<GCodePathSegment>,
     /// 路径的构建器
     last_path_builder: RefCell<Option<lyon_path::Builder>>,
     /// 是否开始了路径
     is_path_begin: bool,
     /// 路径是否有直线
     is_path_line: bool,
+    /// 空走路径的构建器
+    travel_builder: Option<lyon_path::Builder>,
+    /// 空走路径最后的位置, 有值时表示开始了路径
+    travel_last: Option<Point>,
 }
 
 impl Default for GCodeValueHandlerPath {
     fn default() -> Self {
         GCodeValueHandlerPath {
             layers: vec![],
-            mm_value_scale: 1.0,
-            is_relative: false,
-            last_x: 0.0,
-            last_y: 0.0,
+            modal: GCodeModalState::default(),
+            coordinates: GCodeCoordinates::default(),
+            laser_mode: false,
+            rapid_laser_off: true,
+            layer_comments: false,
+            filament_length: 0.0,
             z: None,
+            segments: vec![],
             last_path_builder: RefCell::new(None),
             is_path_begin: false,
             is_path_line: false,
+            travel_builder: None,
+            travel_last: None,
         }
     }
 }
 
 impl GCodeValueHandlerPath {
+    /// 挤出的耗材重量, 单位g
+    /// - [diameter] 耗材的直径, 单位mm
+    /// - [density] 耗材的密度, 单位g/cm³
+    pub fn filament_weight(&self, diameter: f64, density: f64) -> f64 {
+        filament_weight(self.filament_length, diameter, density)
+    }
+
     /// 移动到指定位置
-    fn move_to(&mut self, gcode_value_line: &Vec<GCodeValue>) {
-        let x_value = gcode_value_line
-            .iter()
-            .find(|gcode_value| gcode_value.is_x());
-        let y_value = gcode_value_line
-            .iter()
-            .find(|gcode_value| gcode_value.is_y());
-        //--
-        let x = if let Some(x_value) = x_value {
-            let x = x_value.value_f32() * self.mm_value_scale;
-            if self.is_relative { x + self.last_x } else { x }
-        } else {
-            self.last_x
-        };
-        let y = if let Some(y_value) = y_value {
-            let y = y_value.value_f32() * self.mm_value_scale;
-            if self.is_relative { y + self.last_y } else { y }
-        } else {
-            self.last_y
-        };
+    fn move_to(&mut self, motion: &GCodeMotion) {
+        let (x, y) = (motion.to.x as f32, motion.to.y as f32);
         if self.last_path_builder.borrow().is_none() {
             self.last_path_builder = RefCell::new(Some(lyon_path::Builder::new()));
             self.is_path_begin = false;
@@ -123,44 +192,179 @@ impl GCodeValueHandlerPath {
         }
     }
 
-    /// 连接到指定位置
-    fn line_to(&mut self, gcode_value_line: &Vec<GCodeValue>) {
-        let x_value = gcode_value_line
-            .iter()
-            .find(|gcode_value| gcode_value.is_x());
-        let y_value = gcode_value_line
-            .iter()
-            .find(|gcode_value| gcode_value.is_y());
-        //--
-        let x = if let Some(x_value) = x_value {
-            let x = x_value.value_f32() * self.mm_value_scale;
-            if self.is_relative { x + self.last_x } else { x }
-        } else {
-            self.last_x
-        };
-        let y = if let Some(y_value) = y_value {
-            let y = y_value.value_f32() * self.mm_value_scale;
-            if self.is_relative { y + self.last_y } else { y }
-        } else {
-            self.last_y
-        };
+    /// 获取路径构建器, 没有开始路径时从运动的起点开始
+    fn path_builder(&mut self, motion: &GCodeMotion) -> &mut lyon_path::Builder {
         if self.last_path_builder.borrow().is_none() {
             self.last_path_builder = RefCell::new(Some(lyon_path::Builder::new()));
             self.is_path_begin = false;
             self.is_path_line = false;
-            if let Some(last_path_builder) = self.last_path_builder.get_mut() {
-                self.is_path_begin = true;
-                last_path_builder.begin(point(x, y));
+        }
+        let last_path_builder = self.last_path_builder.get_mut().as_mut().unwrap();
+        if !self.is_path_begin {
+            self.is_path_begin = true;
+            last_path_builder.begin(point(motion.from.x as f32, motion.from.y as f32));
+        }
+        self.is_path_line = true;
+        last_path_builder
+    }
+
+    /// 记录一段运动的属性
+    fn push_segment(&mut self, motion: &GCodeMotion) {
+        self.segments.push(GCodePathSegment {
+            mode: motion.mode,
+            from: motion.from,
+            to: motion.to,
+            feed_rate: self.modal.feed_rate_mm(),
+            power: self.modal.spindle_speed,
+            laser_on: self.modal.is_spindle_on(),
+            tool: self.modal.tool,
+            extrusion: motion.extrusion,
+            cycle: motion.cycle,
+        });
+    }
+
+    /// 连接到指定位置
+    fn line_to(&mut self, motion: &GCodeMotion) {
+        let to = point(motion.to.x as f32, motion.to.y as f32);
+        self.path_builder(motion).line_to(to);
+        self.push_segment(motion);
+    }
+
+    /// 圆弧连接到指定位置, 路径为圆弧在XY平面的投影
+    /// - 螺旋线的Z在[GCodePathSegment]的起点和终点中
+    /// - 其他平面的圆弧投影之后不是圆弧, 按照[ARC_FLATTEN_TOLERANCE]展平成直线
+    fn arc_to(&mut self, motion: &GCodeMotion) {
+        let Some(arc) = motion.arc else {
+            self.line_to(motion);
+            return;
+        };
+        if arc.plane != GCodePlane::XY {
+            let last_path_builder = self.path_builder(motion);
+            for to in motion.flatten(ARC_FLATTEN_TOLERANCE) {
+                last_path_builder.line_to(point(to.x as f32, to.y as f32));
             }
-        } else {
-            if let Some(last_path_builder) = self.last_path_builder.get_mut() {
-                self.is_path_line = true;
-                last_path_builder.line_to(point(x, y));
+            self.push_segment(motion);
+            return;
+        }
+        let arc = Arc {
+            center: point(arc.cx as f32, arc.cy as f32),
+            radii: vector(arc.radius as f32, arc.radius as f32),
+            start_angle: Angle::radians(arc.start_angle as f32),
+            sweep_angle: Angle::radians(arc.sweep_angle as f32),
+            x_rotation: Angle::zero(),
+        };
+        let last_path_builder = self.path_builder(motion);
+        arc.for_each_cubic_bezier(&mut |segment| {
+            last_path_builder.cubic_bezier_to(segment.ctrl1, segment.ctrl2, segment.to);
+        });
+        self.push_segment(motion);
+    }
+
+    /// 空走到指定位置, 圆弧也使用直线连接
+    fn travel_to(&mut self, motion: &GCodeMotion) {
+        let from = point(motion.from.x as f32, motion.from.y as f32);
+        let to = point(motion.to.x as f32, motion.to.y as f32);
+        let builder = self
+            .travel_builder
+            .get_or_insert_with(lyon_path::Builder::new);
+        if self.travel_last != Some(from) {
+            if self.travel_last.is_some() {
+                builder.end(false);
+            }
+            builder.begin(from);
+        }
+        builder.line_to(to);
+        self.travel_last = Some(to);
+    }
+
+    /// 取出空走路径, 没有则返回空路径
+    fn take_travel_path(&mut self) -> Path {
+        match self.travel_builder.take() {
+            Some(mut builder) => {
+                if self.travel_last.take().is_some() {
+                    builder.end(false);
+                }
+                builder.build()
+            }
+            None => Path::new(),
+        }
+    }
+
+    /// 样条连接到指定位置, 只支持XY平面
+    fn spline_to(&mut self, motion: &GCodeMotion) {
+        let to = point(motion.to.x as f32, motion.to.y as f32);
+        let ctrl = |(x, y): (f64, f64)| point(x as f32, y as f32);
+        match motion.spline {
+            Some(GCodeSpline::Cubic { ctrl1, ctrl2 }) => {
+                self.path_builder(motion)
+                    .cubic_bezier_to(ctrl(ctrl1), ctrl(ctrl2), to);
+            }
+            Some(GCodeSpline::Quadratic { ctrl: c }) => {
+                self.path_builder(motion).quadratic_bezier_to(ctrl(c), to);
+            }
+            None => {
+                self.path_builder(motion).line_to(to);
+            }
+        }
+        self.push_segment(motion);
+    }
+
+    /// 处理一段运动
+    /// - [gcode_value_line] 运动所在的行
+    fn handle_motion(&mut self, mut motion: GCodeMotion, gcode_value_line: &[GCodeValue]) {
+        self.filament_length += motion.extrusion;
+        if self.coordinates == GCodeCoordinates::Work {
+            motion = motion.translate(&self.modal.work_offset());
+        }
+        if motion.cycle.is_some() && !motion.have_xy() {
+            //固定循环中Z的移动不会分层, 孔底的进给记录为一个点
+            if self
+                .modal
+                .is_cut(motion.mode, self.laser_mode, self.rapid_laser_off)
+            {
+                self.line_to(&motion);
+            }
+            return;
+        }
+        if !motion.have_xy() {
+            if motion.params.z.is_some() {
+                if !self.layer_comments {
+                    //只有Z的移动, 有层了
+                    self.append_last_layer();
+                } else if self.is_path_line {
+                    //z-hop
+                    return;
+                }
+                self.z = gcode_value_line.iter().find(|v| v.is_z()).map(|z| {
+                    let mut z = z.clone();
+                    z.value = format_number(motion.to.z, 6);
+                    z
+                });
+            }
+            return;
+        }
+        if motion.mode == GCodeMotionMode::Cancel {
+            return;
+        }
+        if !self
+            .modal
+            .is_cut(motion.mode, self.laser_mode, self.rapid_laser_off)
+        {
+            self.move_to(&motion);
+            self.travel_to(&motion);
+            return;
+        }
+        match motion.mode {
+            GCodeMotionMode::ArcCw | GCodeMotionMode::ArcCcw => self.arc_to(&motion),
+            GCodeMotionMode::CubicSpline | GCodeMotionMode::QuadraticSpline => {
+                self.spline_to(&motion)
             }
+            _ => self.line_to(&motion),
         }
     }
 
     /// 追加最后一层, 如果有
+    /// - 没有切割的层不会追加, 空走路径会合并到下一层
     fn append_last_layer(&mut self) {
         if (self.is_path_line) {
             //之前收集到了数据
@@ -169,13 +373,17 @@ impl GCodeValueHandlerPath {
                     last_path_builder.end(false);
                 }
                 let path = last_path_builder.build();
+                let travel_path = self.take_travel_path();
                 self.layers.push(GCodeValueHandlerPathLayer {
                     z: self.z.clone(),
                     path,
+                    travel_path,
+                    segments: std::mem::take(&mut self.segments),
                 });
             }
         }
         self.z = None;
+        self.segments.clear();
         self.last_path_builder = RefCell::new(None);
         self.is_path_begin = false;
         self.is_path_line = false;
@@ -186,8 +394,43 @@ impl GCodeValueHandlerPath {
 pub struct GCodeValueHandlerPathLayer {
     /// 当前层Z坐标, 如果有
     pub z: Option<GCodeValue>,
-    /// 核心路径数据
+    /// 核心路径数据, 切割的运动
     pub path: Path,
+    /// 空走的运动, 没有则为空路径
+    pub travel_path: Path,
+    /// 路径中每一段直线/圆弧的属性, 按照路径的顺序
+    /// - 一行`G1`/`G2`/`G3`/`G5`/`G5.1`对应一段, 圆弧在[path]中会有多段曲线
+    pub segments: Vec<GCodePathSegment>,
+}
+
+/// 一段路径的属性
+#[derive(Clone, Debug, PartialEq)]
+pub struct GCodePathSegment {
+    /// 运动模式
+    pub mode: GCodeMotionMode,
+    /// 起点, 单位mm
+    pub from: GCodePosition,
+    /// 终点, 单位mm
+    pub to: GCodePosition,
+    /// 进给速度, 单位mm/min
+    pub feed_rate: f64,
+    /// 激光功率/主轴转速`S`
+    pub power: f64,
+    /// 激光/主轴是否打开
+    pub laser_on: bool,
+    /// 刀具编号
+    pub tool: u32,
+    /// 挤出的长度, 单位mm, 负数表示回抽
+    pub extrusion: f64,
+    /// 由固定循环展开的运动所属的循环
+    pub cycle: Option<GCodeCycle>,
+}
+
+impl GCodePathSegment {
+    /// 挤出状态
+    pub fn extrusion_state(&self) -> GCodeExtrusionState {
+        GCodeExtrusionState::from_extrusion(self.extrusion)
+    }
 }
 
 impl GCodeValueHandlerPathLayer {
@@ -208,65 +451,50 @@ impl GCodeValueHandlerPathLayer {
         }
         0.0
     }
+
+    /// 当前层激光打开时的最大功率, 没有则返回0
+    pub fn max_power(&self) -> f64 {
+        self.segments
+            .iter()
+            .filter(|s| s.laser_on)
+            .map(|s| s.power)
+            .fold(0.0, f64::max)
+    }
+
+    /// 当前层的最大进给速度, 单位mm/min, 没有则返回0
+    pub fn max_feed_rate(&self) -> f64 {
+        self.segments
+            .iter()
+            .map(|s| s.feed_rate)
+            .fold(0.0, f64::max)
+    }
 }
 
 impl GCodeValueHandler for GCodeValueHandlerPath {
-    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) {
-        for gcode_value in &gcode_value_line {
-            let cmd = gcode_value.to_string();
-            match cmd.as_str() {
-                "G90" => {
-                    //绝对位置
-                    self.is_relative = false;
-                }
-                "G91" => {
-                    //相对位置
-                    self.is_relative = true;
-                }
-                "G20" => {
-                    // inch数值
-                    self.mm_value_scale = 25.4;
-                }
-                "G21" => {
-                    // mm数值
-                    self.mm_value_scale = 1.0;
-                }
-                "G0" => {
-                    let have_xy = gcode_value_line
-                        .iter()
-                        .any(|gcode_value| gcode_value.is_xy());
-                    if have_xy {
-                        self.move_to(&gcode_value_line);
-                        break;
-                    }
-                }
-                "G1" => {
-                    let have_xy = gcode_value_line
-                        .iter()
-                        .any(|gcode_value| gcode_value.is_xy());
-                    if have_xy {
-                        self.line_to(&gcode_value_line);
-                        break;
-                    }
-                }
-                _ => {
-                    if match gcode_value.command.as_str() {
-                        "Z" => {
-                            //有层了
-                            self.append_last_layer();
-                            self.z = Some(gcode_value.clone());
-                            true
-                        }
-                        _ => false,
-                    } {
-                        break;
-                    }
-                }
+    fn handle_comment(&mut self, comment: &GCodeComment) {
+        if self.layer_comments && is_layer_comment(comment) {
+            self.append_last_layer();
+        }
+    }
+
+    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+        let commands = GCodeCommand::from_values(&gcode_value_line);
+        for commands in self.modal.expand_cycle(&commands) {
+            if let Some(motion) = self.modal.apply(&commands) {
+                self.handle_motion(motion, &gcode_value_line);
             }
         }
+        GCodeFlow::Continue
     }
 
     fn end(&mut self) {
-        self.append_last_layer()
+        self.append_last_layer();
+        //最后的空走合并到最后一层
+        let travel_path = self.take_travel_path();
+        if let Some(layer) = self.layers.last_mut() {
+            let mut builder = Path::builder();
+            builder.extend_from_paths(&[layer.travel_path.as_slice(), travel_path.as_slice()]);
+            layer.travel_path = builder.build();
+        }
     }
 }
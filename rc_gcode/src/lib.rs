@@ -1,14 +1,30 @@
+use crate::arc_fit::GCodeArcFitter;
+use crate::depth::GCodeDepthOptions;
+use crate::dialect::GCodeDialect;
 use crate::writer::{GCodeWriter, SvgPathWriter};
 use lyon_algorithms::aabb::fast_bounding_box;
-use lyon_algorithms::walk::{walk_along_path, RegularPattern, WalkerEvent};
+use lyon_algorithms::walk::{RegularPattern, WalkerEvent, walk_along_path};
 use lyon_path::iterator::PathIterator;
 
+pub mod arc_fit;
+pub mod checksum;
+pub mod command;
+pub mod cycle;
+pub mod depth;
+pub mod diagnostic;
+pub mod dialect;
 pub mod handler;
+pub mod ild;
 pub mod lines;
+pub mod metadata;
+pub mod modal;
 pub mod parser;
+pub mod pipeline;
+pub mod planner;
+pub mod stats;
+pub mod svg;
 pub mod writer;
 pub mod ydd;
-pub mod ild;
 
 /// 将有多个轮廓的[Path]拆成单轮廓的[Path]
 pub fn split_path_contours(path: &lyon_path::Path) -> Vec<lyon_path::Path> {
@@ -81,6 +97,184 @@ pub fn path_to_gcode(
     writer.to_string()
 }
 
+/// 将[Path]转换成GCode, 展平之后在同一个圆弧上的点使用`G2`/`G3`
+/// - 支持多轮廓
+/// - 闭合的轮廓会连接回起点
+///
+/// - [tolerance] 圆弧拟合的公差 0.01, 使用一半的公差展平, 保证每一段直线到圆弧的距离也在公差内
+/// - [digit] GCode小数点位数
+pub fn path_to_gcode_arcs(
+    path: &lyon_path::Path,
+    tolerance: f32,
+    digit: usize,
+    begin: &str,
+) -> String {
+    let fitter = GCodeArcFitter {
+        tolerance: tolerance as f64,
+        ..Default::default()
+    };
+    let mut writer = GCodeWriter::new(digit);
+    if !begin.is_empty() {
+        writer.write_line(begin);
+    }
+    let mut points = vec![];
+    path.iter()
+        .flattened(tolerance / 2.0)
+        .for_each(|event| match event {
+            lyon_path::Event::Begin { at } => {
+                writer.move_to(at.x as f64, at.y as f64);
+            }
+            lyon_path::Event::Line { to, .. } => {
+                points.push((to.x as f64, to.y as f64));
+            }
+            lyon_path::Event::End { last, first, close } => {
+                if close && last != first {
+                    points.push((first.x as f64, first.y as f64));
+                }
+                writer.polyline_to(&points, &fitter);
+                points.clear();
+            }
+            _ => {}
+        });
+    writer.to_string()
+}
+
+/// 按照固件方言将[Path]转换成GCode, 输出方言的开头和结尾
+/// - 每个轮廓切割之前打开激光, 之后关闭激光, 只有起点的轮廓不会打开激光
+/// - 方言支持圆弧时, 展平之后在同一个圆弧上的点使用`G2`/`G3`, 参考[path_to_gcode_arcs]
+/// - 闭合的轮廓会连接回起点
+///
+/// - [tolerance] 公差 0.01
+/// - [digit] GCode小数点位数
+/// - [power] 激光功率`0~1`
+/// - [feed_rate] 切割的进给速度`F`
+pub fn path_to_gcode_dialect(
+    path: &lyon_path::Path,
+    tolerance: f32,
+    digit: usize,
+    dialect: &GCodeDialect,
+    power: f64,
+    feed_rate: f64,
+) -> String {
+    let fitter = GCodeArcFitter {
+        tolerance: tolerance as f64,
+        ..Default::default()
+    };
+    let mut writer = GCodeWriter::with_dialect(digit, dialect.clone());
+    writer.compact = true;
+    writer.begin();
+    let mut points = vec![];
+    path.iter()
+        .flattened(tolerance / 2.0)
+        .for_each(|event| match event {
+            lyon_path::Event::Begin { at } => {
+                writer.set_feed_rate(None);
+                writer.move_to(at.x as f64, at.y as f64);
+            }
+            lyon_path::Event::Line { to, .. } => {
+                points.push((to.x as f64, to.y as f64));
+            }
+            lyon_path::Event::End { last, first, close } => {
+                if close && last != first {
+                    points.push((first.x as f64, first.y as f64));
+                }
+                //只有起点的轮廓不需要切割
+                if !points.is_empty() {
+                    writer.laser_on(power);
+                    writer.set_feed_rate(Some(feed_rate));
+                    writer.polyline_to(&points, &fitter);
+                    writer.laser_off();
+                    points.clear();
+                }
+            }
+            _ => {}
+        });
+    writer.end();
+    writer.to_string()
+}
+
+/// 将[Path]转换成多次切割的GCode, 每一次Z下降一层, 参考[GCodeDepthOptions]
+/// - 支持多轮廓, 每个轮廓切割完所有的深度之后抬刀到安全高度, 再切割下一个轮廓
+/// - 闭合的轮廓会连接回起点
+///
+/// - [tolerance] 公差 0.01
+/// - [digit] GCode小数点位数
+pub fn path_to_gcode_passes(
+    path: &lyon_path::Path,
+    tolerance: f32,
+    digit: usize,
+    begin: &str,
+    options: &GCodeDepthOptions,
+) -> String {
+    let mut writer = GCodeWriter::new(digit);
+    if !begin.is_empty() {
+        writer.write_line(begin);
+    }
+    writer.z_to(options.safe_z, true);
+    let mut points = vec![];
+    path.iter()
+        .flattened(tolerance)
+        .for_each(|event| match event {
+            lyon_path::Event::Begin { at } => {
+                points.push((at.x as f64, at.y as f64));
+            }
+            lyon_path::Event::Line { to, .. } => {
+                points.push((to.x as f64, to.y as f64));
+            }
+            lyon_path::Event::End { last, first, close } => {
+                if close && last != first {
+                    points.push((first.x as f64, first.y as f64));
+                }
+                let closed = close || (points.len() > 2 && last == first);
+                options.write_contour(&mut writer, &points, closed);
+                points.clear();
+            }
+            _ => {}
+        });
+    writer.to_string()
+}
+
+/// 将[Path]转换成GCode, 曲线不展平
+/// - 二次曲线使用`G5.1`, 三次曲线使用`G5`, 需要固件支持, 比如LinuxCNC
+/// - 闭合的轮廓会连接回起点
+///
+/// - [digit] GCode小数点位数
+pub fn path_to_gcode_curves(path: &lyon_path::Path, digit: usize, begin: &str) -> String {
+    let mut writer = GCodeWriter::new(digit);
+    if !begin.is_empty() {
+        writer.write_line(begin);
+    }
+    path.iter().for_each(|event| match event {
+        lyon_path::Event::Begin { at } => {
+            writer.move_to(at.x as f64, at.y as f64);
+        }
+        lyon_path::Event::Line { to, .. } => {
+            writer.line_to(to.x as f64, to.y as f64);
+        }
+        lyon_path::Event::Quadratic { ctrl, to, .. } => {
+            writer.quadratic_to(ctrl.x as f64, ctrl.y as f64, to.x as f64, to.y as f64);
+        }
+        lyon_path::Event::Cubic {
+            ctrl1, ctrl2, to, ..
+        } => {
+            writer.cubic_to(
+                ctrl1.x as f64,
+                ctrl1.y as f64,
+                ctrl2.x as f64,
+                ctrl2.y as f64,
+                to.x as f64,
+                to.y as f64,
+            );
+        }
+        lyon_path::Event::End { last, first, close } => {
+            if close && last != first {
+                writer.line_to(first.x as f64, first.y as f64);
+            }
+        }
+    });
+    writer.to_string()
+}
+
 /// 将[Path]转换成svg path数据
 /// - 支持多轮廓
 ///
@@ -168,13 +362,18 @@ pub fn path_bounds(path: &lyon_path::Path) -> (f32, f32, f32, f32) {
 #[cfg(test)]
 mod tests {
     use crate::handler::{GCodeValueHandlerImpl, GCodeValueHandlerPath};
+    use crate::modal::{GCodeCoordinates, GCodeExtrusionState, GCodePosition, filament_weight};
     use crate::parser::GCodeParser;
-    use crate::writer::GCodeWriter;
-    use crate::{path_bounds, path_to_gcode, path_to_svg_path, path_walk_along_to_gcode};
+    use crate::stats::GCodeValueHandlerStatistics;
+    use crate::writer::{GCodeOutput, GCodeWriter, SvgPathWriter};
+    use crate::{
+        path_bounds, path_to_gcode, path_to_gcode_curves, path_to_svg_path,
+        path_walk_along_to_gcode, split_path_contours,
+    };
     use lyon_algorithms::aabb::fast_bounding_box;
     use lyon_path::iterator::PathIterator;
-    use lyon_path::math::point;
-    use lyon_path::{Path, Winding};
+    use lyon_path::math::{Point, point};
+    use lyon_path::{Event, Path, Winding};
     use rc_basis::files::read_file_to_string;
     use rc_basis::test::{get_test_file_path, get_test_output_file_path, save_and_open_file};
 
@@ -244,6 +443,296 @@ mod tests {
         writer.arc_to(-10.0, 0.0, 0.0, 0.0, false);
         println!("{}", writer.to_string());
     }
+
+    #[test]
+    fn test_gcode_arc_path() {
+        let mut writer = GCodeWriter::new(6);
+        writer.move_to(-10.0, 0.0);
+        writer.arc_to(0.0, -10.0, 0.0, 0.0, false);
+        writer.arc_to(10.0, 0.0, 0.0, 0.0, false);
+        writer.arc_to(0.0, 10.0, 0.0, 0.0, false);
+        writer.arc_to(-10.0, 0.0, 0.0, 0.0, false);
+        //R格式的半圆, 整圆, 螺旋线
+        writer.write_line("G0 X50 Y0");
+        writer.write_line("G2 X60 Y0 R5");
+        writer.write_line("G0 X100 Y0");
+        writer.write_line("G2 I-5 J0 Z-1");
+        let gcode = writer.to_string();
+
+        let mut handler = GCodeValueHandlerPath::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_eq!(handler.layers.len(), 1);
+
+        let contours = split_path_contours(&handler.layers[0].path);
+        let expected = [
+            (-10.0, -10.0, 10.0, 10.0),
+            (50.0, 0.0, 60.0, 5.0),
+            (90.0, -5.0, 100.0, 5.0),
+        ];
+        assert_eq!(contours.len(), expected.len());
+        for (contour, expected) in contours.iter().zip(expected) {
+            let bounds = lyon_algorithms::aabb::bounding_box(contour.iter());
+            let bounds = (bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y);
+            for (a, b) in [
+                (bounds.0, expected.0),
+                (bounds.1, expected.1),
+                (bounds.2, expected.2),
+                (bounds.3, expected.3),
+            ] {
+                assert!((a - b).abs() < 0.01, "{:?} != {:?}", bounds, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gcode_modal_path() {
+        let gcode = "G0 X0 Y0\nG1 X10 Y0\nX10 Y10\nY20\nG91\nX-10".to_string();
+        let mut handler = GCodeValueHandlerPath::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_eq!(handler.layers.len(), 1);
+        assert_eq!(path_bounds(&handler.layers[0].path), (0.0, 0.0, 10.0, 20.0));
+        assert_eq!(handler.layers[0].path.iter().count(), 6);
+        assert!(handler.modal.is_relative());
+    }
+
+    #[test]
+    fn test_gcode_path_coordinates() {
+        let gcode = "G55\nG0 X0 Y0\nG1 X10 Y10\nG92 X0 Y0\nG1 X5 Y0".to_string();
+        let mut bounds = vec![];
+        for coordinates in [GCodeCoordinates::Work, GCodeCoordinates::Machine] {
+            let mut handler = GCodeValueHandlerPath::default();
+            handler.coordinates = coordinates;
+            handler.modal.work_offsets[1] = GCodePosition {
+                x: 100.0,
+                y: 50.0,
+                z: 0.0,
+            };
+            GCodeParser::new(&gcode).parse(&mut handler);
+            bounds.push(path_bounds(&handler.layers[0].path));
+        }
+        assert_eq!(
+            bounds,
+            vec![(0.0, 0.0, 10.0, 10.0), (100.0, 50.0, 115.0, 60.0)]
+        );
+    }
+
+    #[test]
+    fn test_gcode_path_travel() {
+        let gcode = "G0 X0 Y0\nM4 S0\nG1 X10\nS500\nG1 Y10\nG0 X0 Y0\nM5\nG1 X5 Y5\nM3 S300\nG0 X10 Y10\nG1 X20 Y10"
+            .to_string();
+        let mut counts = vec![];
+        for rapid_laser_off in [true, false] {
+            let mut handler = GCodeValueHandlerPath::default();
+            handler.laser_mode = true;
+            handler.rapid_laser_off = rapid_laser_off;
+            GCodeParser::new(&gcode).parse(&mut handler);
+            let layer = &handler.layers[0];
+            let travel = layer
+                .travel_path
+                .iter()
+                .filter(|e| matches!(e, Event::Line { .. }));
+            counts.push((layer.segments.len(), travel.count()));
+        }
+        assert_eq!(counts, vec![(2, 5), (3, 4)]);
+
+        //非激光模式只有`G0`是空走
+        let mut handler = GCodeValueHandlerPath::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_eq!(handler.layers[0].segments.len(), 4);
+        assert_eq!(
+            path_bounds(&handler.layers[0].travel_path),
+            (0.0, 0.0, 10.0, 10.0)
+        );
+    }
+
+    #[test]
+    fn test_gcode_spline_path() {
+        let mut builder = Path::builder();
+        builder.begin(point(0.0, 0.0));
+        builder.cubic_bezier_to(point(0.0, 10.0), point(10.0, 10.0), point(10.0, 0.0));
+        builder.quadratic_bezier_to(point(15.0, -5.0), point(20.0, 0.0));
+        builder.end(true);
+        let path = builder.build();
+
+        let gcode = path_to_gcode_curves(&path, 3, "");
+        assert_eq!(
+            gcode,
+            "G0 X0 Y0\nG5 X10 Y0 I0 J10 P0 Q10\nG5.1 X20 Y0 I5 J-5\nG1 X0 Y0"
+        );
+
+        let mut handler = GCodeValueHandlerPath::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let events: Vec<Event<Point, Point>> = handler.layers[0].path.iter().collect();
+        assert_eq!(&events[..3], &path.iter().collect::<Vec<_>>()[..3]);
+
+        //省略`I`/`J`时, 第一个控制点为上一段第二个控制点的对称点
+        let gcode = "G5 X10 Y0 I0 J10 P0 Q10\nG5 X20 Y0 P0 Q-10".to_string();
+        let mut handler = GCodeValueHandlerPath::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let events: Vec<Event<Point, Point>> = handler.layers[0].path.iter().collect();
+        assert!(matches!(
+            events[2],
+            Event::Cubic { ctrl1, .. } if ctrl1 == point(10.0, -10.0)
+        ));
+    }
+
+    #[test]
+    fn test_gcode_path_extrusion() {
+        let gcode =
+            "M83\nG1 X10 E1\nG1 E-0.5\nG0 X20\nG1 X30 E0.5\nM82\nG92 E0\nG1 X40 E2\nG1 X50 E1.5"
+                .to_string();
+        let mut handler = GCodeValueHandlerPath::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let states: Vec<GCodeExtrusionState> = handler.layers[0]
+            .segments
+            .iter()
+            .map(|s| s.extrusion_state())
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                GCodeExtrusionState::Extrude,
+                GCodeExtrusionState::Extrude,
+                GCodeExtrusionState::Extrude,
+                GCodeExtrusionState::Retract,
+            ]
+        );
+        assert_eq!(handler.filament_length, 2.5);
+        //1.75mm的PLA, 1m约3g
+        let weight = filament_weight(1000.0, 1.75, 1.24);
+        assert!((weight - 2.98).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_gcode_path_segment() {
+        let gcode =
+            "T1\nG0 X0 Y0\nM3 S100\nG1 X10 F600\nG2 X20 I5 S800\nM5\nG1 Y10 F1200".to_string();
+        let mut handler = GCodeValueHandlerPath::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let layer = &handler.layers[0];
+        let segments: Vec<(f64, f64, bool, u32)> = layer
+            .segments
+            .iter()
+            .map(|s| (s.feed_rate, s.power, s.laser_on, s.tool))
+            .collect();
+        assert_eq!(
+            segments,
+            vec![
+                (600.0, 100.0, true, 1),
+                (600.0, 800.0, true, 1),
+                (1200.0, 800.0, false, 1),
+            ]
+        );
+        assert_eq!(layer.max_power(), 800.0);
+        assert_eq!(layer.max_feed_rate(), 1200.0);
+    }
+
+    #[test]
+    fn test_gcode_writer_line_number() {
+        let mut writer = GCodeWriter::new(6);
+        writer.reset_line_number(0);
+        writer.write_line("G90\nG21");
+        writer.line_to(1.0, 2.0);
+        //空行和注释原样输出, 校验和放在注释之前
+        writer.write_line("");
+        writer.write_line(";off");
+        writer.write_line("(header comment)");
+        writer.write_line("M5 ;off");
+        let gcode = writer.to_string();
+        assert_eq!(
+            gcode,
+            "N0 M110 N0*125\nN1 G90*17\nN2 G21*24\nN3 G1 X1 Y2*41\n\n;off\n(header comment)\nN4 M5*34 ;off"
+        );
+
+        let mut parser = GCodeParser::new(&gcode);
+        parser.validator.check_line_number = true;
+        assert!(
+            parser
+                .try_parse(&mut GCodeValueHandlerImpl::default())
+                .is_ok()
+        );
+    }
+    #[test]
+    fn test_gcode_writer_compact() {
+        let write = |compact: bool| {
+            let mut writer = GCodeWriter::new(3);
+            writer.compact = compact;
+            writer.move_to(0.0, 0.0);
+            writer.set_feed_rate(Some(1000.0));
+            writer.set_power(Some(500.0));
+            writer.line_to(10.0, 0.0);
+            writer.line_to(10.0, 10.0);
+            writer.line_to(10.0, 10.0);
+            writer.set_power(Some(300.0));
+            writer.line_to(0.0, 10.0);
+            writer.arc_to(0.0, 0.0, 0.0, 5.0, false);
+            writer.arc_to(0.0, 10.0, 0.0, 5.0, false);
+            writer.move_to(20.0, 20.0);
+            writer.to_string()
+        };
+        let compact = write(true);
+        assert_eq!(
+            compact,
+            "G0 X0 Y0\nG1 X10 F1000 S500\nY10\nX0 S300\nG3 X0 Y0 I0 J-5\nX0 Y10 I0 J5\nG0 X20 Y20"
+        );
+        let full = write(false);
+        assert!(full.starts_with("G0 X0 Y0\nG1 X10 Y0 F1000 S500\nG1 X10 Y10 F1000 S500\n"));
+
+        //解析的结果相同
+        let statistics = |gcode: &String| {
+            let mut handler = GCodeValueHandlerStatistics::default();
+            GCodeParser::new(gcode).parse(&mut handler);
+            handler.statistics
+        };
+        let (compact, full) = (statistics(&compact), statistics(&full));
+        assert_eq!(compact.cut_length, full.cut_length);
+        assert_eq!(compact.bounds, full.bounds);
+        assert_eq!(compact.min_power, Some(300.0));
+        assert_eq!(compact.max_feed_rate, Some(1000.0));
+    }
+
+    #[test]
+    fn test_gcode_writer_io() {
+        fn write_gcode<O: GCodeOutput>(writer: &mut GCodeWriter<O>) {
+            writer.reset_line_number(0);
+            writer.move_to(0.0, 0.0);
+            writer.line_to(10.0, 0.0);
+            writer.arc_to(0.0, 0.0, 5.0, 0.0, true);
+        }
+        let mut writer = GCodeWriter::new(3);
+        write_gcode(&mut writer);
+        let mut io_writer = GCodeWriter::from_writer(vec![], 3);
+        write_gcode(&mut io_writer);
+        let bytes = io_writer.finish().unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), writer.to_string());
+
+        let mut writer = SvgPathWriter::new(3);
+        writer.move_to(0.0, 0.0);
+        writer.line_to(10.0, 0.0);
+        let mut io_writer = SvgPathWriter::from_writer(vec![], 3);
+        io_writer.move_to(0.0, 0.0);
+        io_writer.line_to(10.0, 0.0);
+        let bytes = io_writer.finish().unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), writer.to_string());
+
+        //写入失败
+        struct FailWriter;
+        impl std::io::Write for FailWriter {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("fail"))
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut io_writer = GCodeWriter::from_writer(FailWriter, 3);
+        for i in 0..10000 {
+            io_writer.line_to(i as f64, 0.0);
+        }
+        assert!(io_writer.finish().is_err());
+    }
+
     #[test]
     fn test_path_gcode_writer() {
         let mut writer = GCodeWriter::new(6);
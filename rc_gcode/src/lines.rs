@@ -1,5 +1,5 @@
 use crate::split_path_contours;
-use lyon_algorithms::walk::{walk_along_path, RegularPattern, WalkerEvent};
+use lyon_algorithms::walk::{RegularPattern, WalkerEvent, walk_along_path};
 use lyon_path::iterator::PathIterator;
 
 ///
@@ -0,0 +1,296 @@
+use crate::handler::{GCodeFlow, GCodeValueHandler};
+use crate::parser::{GCodeComment, GCodeValue};
+use base64::Engine;
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use image::DynamicImage;
+use std::collections::BTreeMap;
+
+///
+/// @author <a href="mailto: angcyo@126.com">angcyo</a>
+/// @date 2026/10/18
+///
+/// 切片软件(PrusaSlicer/Cura/OrcaSlicer)写在注释中的元数据
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GCodeMetadata {
+    /// 切片软件, 比如`PrusaSlicer 2.6.0`
+    pub slicer: Option<String>,
+    /// 缩略图
+    pub thumbnails: Vec<GCodeThumbnail>,
+    /// 声明的层数, Cura的`;LAYER_COUNT:`
+    pub layer_count: Option<usize>,
+    /// 层标记`;LAYER:`/`;LAYER_CHANGE`的数量
+    pub layer_markers: usize,
+    /// 出现过的特征类型`;TYPE:`, 按照出现的顺序
+    pub feature_types: Vec<String>,
+    /// 设置项, Prusa的`; key = value`和Cura的`;KEY:value`
+    pub settings: BTreeMap<String, String>,
+}
+
+/// 嵌入的缩略图
+/// - `; thumbnail begin 300x300 12345`
+/// - `; thumbnail_JPG begin 300x300 12345`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GCodeThumbnail {
+    pub width: u32,
+    pub height: u32,
+    /// 图片格式, `PNG`/`JPG`/`QOI`
+    pub format: String,
+    /// base64数据
+    pub data: String,
+}
+
+impl GCodeMetadata {
+    /// 层数, 优先使用声明的层数, 否则为层标记的数量
+    pub fn layers(&self) -> usize {
+        self.layer_count.unwrap_or(self.layer_markers)
+    }
+
+    /// 最大的缩略图
+    pub fn largest_thumbnail(&self) -> Option<&GCodeThumbnail> {
+        self.thumbnails
+            .iter()
+            .max_by_key(|thumbnail| thumbnail.width * thumbnail.height)
+    }
+}
+
+impl GCodeThumbnail {
+    /// 解码成图片
+    /// - 切片软件输出的base64可能有`=`填充, 也可能没有
+    pub fn image(&self) -> Result<DynamicImage, GCodeThumbnailError> {
+        let engine = GeneralPurpose::new(
+            &alphabet::STANDARD,
+            GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+        );
+        let bytes = engine.decode(self.data.trim())?;
+        Ok(image::load_from_memory(&bytes)?)
+    }
+}
+
+/// 缩略图解码出错
+#[derive(Debug)]
+pub enum GCodeThumbnailError {
+    /// base64数据错误
+    Base64(base64::DecodeError),
+    /// 图片数据错误
+    Image(image::ImageError),
+}
+
+impl From<base64::DecodeError> for GCodeThumbnailError {
+    fn from(error: base64::DecodeError) -> Self {
+        Self::Base64(error)
+    }
+}
+
+impl From<image::ImageError> for GCodeThumbnailError {
+    fn from(error: image::ImageError) -> Self {
+        Self::Image(error)
+    }
+}
+
+/// 实现[Display]
+impl std::fmt::Display for GCodeThumbnailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Base64(error) => write!(f, "{}", error),
+            Self::Image(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for GCodeThumbnailError {}
+
+/// 是否是切片软件的层标记注释
+/// - Cura `;LAYER:0`
+/// - PrusaSlicer/OrcaSlicer `;LAYER_CHANGE`
+pub fn is_layer_comment(comment: &GCodeComment) -> bool {
+    !comment.is_paren
+        && (comment.text == "LAYER_CHANGE"
+            || comment
+                .text
+                .strip_prefix("LAYER:")
+                .is_some_and(|n| n.trim().parse::<i64>().is_ok()))
+}
+
+/// 解析缩略图开始的注释, 返回格式和大小
+/// - `thumbnail begin 300x300 12345` -> (`PNG`, 300, 300)
+fn parse_thumbnail_begin(text: &str) -> Option<(String, u32, u32)> {
+    let mut parts = text.split_whitespace();
+    let format = match parts.next()? {
+        "thumbnail" => "PNG".to_string(),
+        tag => tag.strip_prefix("thumbnail_")?.to_uppercase(),
+    };
+    if parts.next()? != "begin" {
+        return None;
+    }
+    let (width, height) = parts.next()?.split_once('x')?;
+    Some((format, width.parse().ok()?, height.parse().ok()?))
+}
+
+/// 提取切片软件的元数据
+/// - 结果在[metadata]中
+#[derive(Default)]
+pub struct GCodeValueHandlerMetadata {
+    /// 元数据
+    pub metadata: GCodeMetadata,
+    //--
+    /// 正在读取的缩略图
+    thumbnail: Option<GCodeThumbnail>,
+}
+
+impl GCodeValueHandlerMetadata {
+    /// 处理设置项和切片软件
+    fn handle_setting(&mut self, text: &str) {
+        let metadata = &mut self.metadata;
+        let lower = text.to_ascii_lowercase();
+        if metadata.slicer.is_none() {
+            if let Some(index) = lower.find("generated by ") {
+                metadata.slicer = Some(text[index + 13..].trim().to_string());
+                return;
+            }
+            if let Some(index) = lower.find("generated with ") {
+                metadata.slicer = Some(text[index + 15..].trim().to_string());
+                return;
+            }
+        }
+        if let Some((key, value)) = text.split_once(" = ") {
+            metadata
+                .settings
+                .insert(key.trim().to_string(), value.trim().to_string());
+        } else if let Some((key, value)) = text.split_once(':') {
+            let is_key = !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ' ');
+            if is_key {
+                metadata
+                    .settings
+                    .insert(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+    }
+}
+
+impl GCodeValueHandler for GCodeValueHandlerMetadata {
+    fn start(&mut self) {
+        self.metadata = GCodeMetadata::default();
+        self.thumbnail = None;
+    }
+
+    fn handle_comment(&mut self, comment: &GCodeComment) {
+        if comment.is_paren {
+            return;
+        }
+        let text = comment.text.as_str();
+
+        //缩略图
+        if let Some(thumbnail) = &mut self.thumbnail {
+            if text.starts_with("thumbnail") && text.ends_with(" end") {
+                let thumbnail = self.thumbnail.take().unwrap();
+                self.metadata.thumbnails.push(thumbnail);
+            } else {
+                thumbnail.data.push_str(text);
+            }
+            return;
+        }
+        if let Some((format, width, height)) = parse_thumbnail_begin(text) {
+            self.thumbnail = Some(GCodeThumbnail {
+                width,
+                height,
+                format,
+                data: String::new(),
+            });
+            return;
+        }
+
+        if is_layer_comment(comment) {
+            self.metadata.layer_markers += 1;
+        } else if let Some(count) = text.strip_prefix("LAYER_COUNT:") {
+            self.metadata.layer_count = count.trim().parse().ok();
+        } else if let Some(feature) = text.strip_prefix("TYPE:") {
+            let feature = feature.trim().to_string();
+            if !self.metadata.feature_types.contains(&feature) {
+                self.metadata.feature_types.push(feature);
+            }
+        } else {
+            self.handle_setting(text);
+        }
+    }
+
+    fn handle_gcode_value(&mut self, _gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+        GCodeFlow::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::GCodeValueHandlerPath;
+    use crate::metadata::{GCodeThumbnail, GCodeThumbnailError, GCodeValueHandlerMetadata};
+    use crate::parser::GCodeParser;
+    use image::GenericImageView;
+
+    #[test]
+    fn test_gcode_metadata() {
+        let gcode = "; generated by PrusaSlicer 2.6.0+win64 on 2026-10-18
+;
+; thumbnail begin 1x1 96
+; iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9
+; awAAAABJRU5ErkJggg==
+; thumbnail end
+;FLAVOR:Marlin
+;LAYER_COUNT:2
+;LAYER:0
+;TYPE:WALL-OUTER
+G1 X1 Y1
+;TYPE:FILL
+;LAYER:1
+;TYPE:WALL-OUTER
+G1 X2
+; layer_height = 0.2
+; filament_type = PLA"
+            .to_string();
+        let mut handler = GCodeValueHandlerMetadata::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let metadata = &handler.metadata;
+        assert_eq!(
+            metadata.slicer.as_deref(),
+            Some("PrusaSlicer 2.6.0+win64 on 2026-10-18")
+        );
+        assert_eq!(metadata.thumbnails.len(), 1);
+        let thumbnail = metadata.largest_thumbnail().unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (1, 1));
+        assert_eq!(thumbnail.format, "PNG");
+        assert_eq!(thumbnail.image().unwrap().dimensions(), (1, 1));
+        let broken = GCodeThumbnail {
+            data: "iVBORw0K*".to_string(),
+            ..thumbnail.clone()
+        };
+        assert!(matches!(
+            broken.image(),
+            Err(GCodeThumbnailError::Base64(_))
+        ));
+        assert_eq!(metadata.layers(), 2);
+        assert_eq!(metadata.layer_markers, 2);
+        assert_eq!(metadata.feature_types, vec!["WALL-OUTER", "FILL"]);
+        assert_eq!(metadata.settings["FLAVOR"], "Marlin");
+        assert_eq!(metadata.settings["layer_height"], "0.2");
+        assert_eq!(metadata.settings["filament_type"], "PLA");
+    }
+
+    #[test]
+    fn test_gcode_layer_comment_path() {
+        //z-hop不会分层
+        let gcode =
+            ";LAYER:0\nG0 Z0.2\nG1 X10\nG0 Z0.6\nG0 X20\nG0 Z0.2\nG1 X30\n;LAYER:1\nG0 Z0.4\nG1 X0"
+                .to_string();
+        let mut handler = GCodeValueHandlerPath::default();
+        handler.layer_comments = true;
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let z: Vec<f64> = handler.layers.iter().map(|l| l.z_f64()).collect();
+        assert_eq!(z, vec![0.2, 0.4]);
+
+        let mut handler = GCodeValueHandlerPath::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_eq!(handler.layers.len(), 3);
+    }
+}
@@ -0,0 +1,932 @@
+use crate::command::{
+    GCodeCommand, GCodeCycle, GCodeDistanceMode, GCodeFeedMode, GCodeParams, GCodePlane, GCodeUnits,
+};
+use crate::cycle::GCodeCycleState;
+use crate::parser::GCodeValue;
+use lyon_path::geom::{CubicBezierSegment, LineSegment, QuadraticBezierSegment, point};
+use std::f64::consts::TAU;
+
+///
+/// @author <a href="mailto: angcyo@126.com">angcyo</a>
+/// @date 2026/10/18
+///
+/// GCode模态状态, 在行与行之间保持
+/// - 运动模式 / 圆弧平面 / 单位 / 坐标模式 / 进给模式
+/// - 主轴/激光的状态 / 刀具编号
+/// - 工件坐标系`G54`~`G59` / `G92`偏移 / 原点
+/// - 固定循环`G73`/`G81`~`G89`的状态
+/// - 当前的位置, 机床坐标, 单位mm
+#[derive(Clone, Debug, Default)]
+pub struct GCodeModalState {
+    /// 运动模式
+    pub motion_mode: GCodeMotionMode,
+    /// 圆弧平面
+    pub plane: GCodePlane,
+    /// 数值单位
+    pub units: GCodeUnits,
+    /// 坐标模式
+    pub distance_mode: GCodeDistanceMode,
+    /// 挤出轴的坐标模式, `G90`/`G91`和`M82`/`M83`都会修改
+    pub extrusion_mode: GCodeDistanceMode,
+    /// 进给速度模式
+    pub feed_mode: GCodeFeedMode,
+    /// 当前的进给速度`F`, 原始数值
+    pub feed_rate: f64,
+    /// 当前的主轴转速/激光功率`S`
+    pub spindle_speed: f64,
+    /// 主轴/激光的状态
+    pub spindle: GCodeSpindleState,
+    /// 当前的刀具编号
+    pub tool: u32,
+    /// 当前的工件坐标系, 0表示`G54`
+    pub coordinate_system: usize,
+    /// 每个工件坐标系原点的机床坐标, 单位mm
+    /// - 通常保存在机器上, 需要在解析之前设置
+    pub work_offsets: [GCodePosition; WORK_COORDINATE_COUNT],
+    /// `G92`设置的偏移, 单位mm
+    pub position_offset: GCodePosition,
+    /// `G28`回到的原点, 机床坐标, 单位mm
+    pub home_position: GCodePosition,
+    /// 当前的位置, 机床坐标, 单位mm
+    pub position: GCodePosition,
+    /// 挤出轴的位置, 单位mm, `G92 E`会重置
+    pub e: f64,
+    /// 固定循环的状态, 参考[GCodeModalState::expand_cycle]
+    pub cycle: GCodeCycleState,
+    //--
+    /// 上一段`G5`的第二个控制点, 用来计算下一段`G5`的第一个控制点
+    spline_ctrl: Option<(f64, f64)>,
+}
+
+/// 工件坐标系的数量, `G54`~`G59`
+pub const WORK_COORDINATE_COUNT: usize = 6;
+
+/// 输出的坐标系
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GCodeCoordinates {
+    /// 工件坐标, 也就是GCode中看到的坐标
+    #[default]
+    Work,
+    /// 机床坐标
+    Machine,
+}
+
+/// 运动模式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GCodeMotionMode {
+    /// `G0` 快速移动
+    #[default]
+    Rapid,
+    /// `G1` 直线插补
+    Linear,
+    /// `G2` 顺时针圆弧
+    ArcCw,
+    /// `G3` 逆时针圆弧
+    ArcCcw,
+    /// `G5` 三次B样条
+    CubicSpline,
+    /// `G5.1` 二次B样条
+    QuadraticSpline,
+    /// `G80` 取消运动模式, 之后只有坐标的行不会运动
+    Cancel,
+}
+
+/// 主轴/激光的状态
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GCodeSpindleState {
+    /// `M5` 关闭
+    #[default]
+    Off,
+    /// `M3` 顺时针, 激光为恒定功率模式
+    Clockwise,
+    /// `M4` 逆时针, 激光为动态功率模式
+    CounterClockwise,
+}
+
+/// 位置, 单位mm
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GCodePosition {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl std::ops::Add for GCodePosition {
+    type Output = GCodePosition;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        GCodePosition {
+            x: self.x + rhs.x,
+            y: self.y + rhs.y,
+            z: self.z + rhs.z,
+        }
+    }
+}
+
+impl std::ops::Sub for GCodePosition {
+    type Output = GCodePosition;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        GCodePosition {
+            x: self.x - rhs.x,
+            y: self.y - rhs.y,
+            z: self.z - rhs.z,
+        }
+    }
+}
+
+/// 一行数据解析出来的运动
+#[derive(Clone, Debug, PartialEq)]
+pub struct GCodeMotion {
+    /// 运动模式
+    pub mode: GCodeMotionMode,
+    /// 起点
+    pub from: GCodePosition,
+    /// 终点
+    pub to: GCodePosition,
+    /// 圆弧信息, `G2`/`G3`才有
+    pub arc: Option<GCodeArc>,
+    /// 样条信息, 只有`G5`/`G5.1`才有
+    pub spline: Option<GCodeSpline>,
+    /// 挤出的长度, 单位mm, 负数表示回抽
+    pub extrusion: f64,
+    /// 由固定循环展开的运动所属的循环
+    pub cycle: Option<GCodeCycle>,
+    /// 原始参数
+    pub params: GCodeParams,
+}
+
+/// XY平面的样条, 控制点为机床坐标, 单位mm
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GCodeSpline {
+    /// `G5` 三次贝塞尔曲线
+    Cubic {
+        ctrl1: (f64, f64),
+        ctrl2: (f64, f64),
+    },
+    /// `G5.1` 二次贝塞尔曲线
+    Quadratic { ctrl: (f64, f64) },
+}
+
+/// 运动的挤出状态
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GCodeExtrusionState {
+    /// 挤出
+    Extrude,
+    /// 回抽
+    Retract,
+    /// 空走, 没有挤出
+    Travel,
+}
+
+/// [plane]平面的圆弧, 单位mm
+/// - 平面中的两个轴, XY平面为(X, Y), ZX平面为(Z, X), YZ平面为(Y, Z)
+/// - 螺旋线垂直于平面的轴从起点线性移动到终点
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GCodeArc {
+    /// 圆弧所在的平面
+    pub plane: GCodePlane,
+    /// 圆心, 平面中的两个轴
+    pub cx: f64,
+    pub cy: f64,
+    /// 半径
+    pub radius: f64,
+    /// 起点的角度, 弧度
+    pub start_angle: f64,
+    /// 扫过的角度, 弧度, 负数表示顺时针
+    pub sweep_angle: f64,
+}
+
+/// 耗材的重量, 单位g
+/// - [length] 耗材的长度, 单位mm
+/// - [diameter] 耗材的直径, 单位mm, 比如1.75
+/// - [density] 耗材的密度, 单位g/cm³, 比如PLA为1.24
+pub fn filament_weight(length: f64, diameter: f64, density: f64) -> f64 {
+    let radius = diameter / 2.0;
+    //mm³ -> cm³
+    std::f64::consts::PI * radius * radius * length / 1000.0 * density
+}
+
+/// 圆弧计算的误差
+const ARC_EPSILON: f64 = 1e-6;
+
+/// 投影到XY平面时, 其他平面的圆弧展平的误差, 单位mm
+pub const ARC_FLATTEN_TOLERANCE: f64 = 0.01;
+
+/// 位置在平面中的两个轴和垂直于平面的轴
+fn plane_axes(plane: GCodePlane, position: &GCodePosition) -> (f64, f64, f64) {
+    match plane {
+        GCodePlane::XY => (position.x, position.y, position.z),
+        GCodePlane::ZX => (position.z, position.x, position.y),
+        GCodePlane::YZ => (position.y, position.z, position.x),
+    }
+}
+
+/// [plane_axes]的逆运算
+fn plane_position(plane: GCodePlane, a: f64, b: f64, c: f64) -> GCodePosition {
+    match plane {
+        GCodePlane::XY => GCodePosition { x: a, y: b, z: c },
+        GCodePlane::ZX => GCodePosition { x: b, y: c, z: a },
+        GCodePlane::YZ => GCodePosition { x: c, y: a, z: b },
+    }
+}
+
+impl GCodeMotion {
+    /// XY平面上是否有移动
+    pub fn have_xy(&self) -> bool {
+        self.params.x.is_some()
+            || self.params.y.is_some()
+            || self.arc.is_some()
+            || self.spline.is_some()
+    }
+
+    /// 运动的长度, 单位mm, 样条为近似值
+    pub fn length(&self) -> f64 {
+        let dz = self.to.z - self.from.z;
+        if let Some(arc) = &self.arc {
+            let (_, _, from) = plane_axes(arc.plane, &self.from);
+            let (_, _, to) = plane_axes(arc.plane, &self.to);
+            return arc.length().hypot(to - from);
+        }
+        match self.spline {
+            Some(GCodeSpline::Cubic { ctrl1, ctrl2 }) => CubicBezierSegment {
+                from: point(self.from.x, self.from.y),
+                ctrl1: point(ctrl1.0, ctrl1.1),
+                ctrl2: point(ctrl2.0, ctrl2.1),
+                to: point(self.to.x, self.to.y),
+            }
+            .approximate_length(ARC_EPSILON)
+            .hypot(dz),
+            Some(GCodeSpline::Quadratic { ctrl }) => QuadraticBezierSegment {
+                from: point(self.from.x, self.from.y),
+                ctrl: point(ctrl.0, ctrl.1),
+                to: point(self.to.x, self.to.y),
+            }
+            .length()
+            .hypot(dz),
+            None => (self.to.x - self.from.x)
+                .hypot(self.to.y - self.from.y)
+                .hypot(dz),
+        }
+    }
+
+    /// 圆弧上的位置, 螺旋线垂直于平面的轴线性移动
+    /// - [t] `0~1`, 0为起点, 1为终点
+    pub fn arc_position(&self, arc: &GCodeArc, t: f64) -> GCodePosition {
+        let (_, _, from) = plane_axes(arc.plane, &self.from);
+        let (_, _, to) = plane_axes(arc.plane, &self.to);
+        let angle = arc.start_angle + arc.sweep_angle * t;
+        plane_position(
+            arc.plane,
+            arc.cx + arc.radius * angle.cos(),
+            arc.cy + arc.radius * angle.sin(),
+            from + (to - from) * t,
+        )
+    }
+
+    /// 将运动拆分成直线, 返回每一段直线的终点
+    /// - 圆弧按照弦高误差分段, 样条按照误差展平
+    /// - [tolerance] 误差, 单位mm
+    pub fn flatten(&self, tolerance: f64) -> Vec<GCodePosition> {
+        let (from, to) = (self.from, self.to);
+        let dz = to.z - from.z;
+        let position = |x: f64, y: f64, t: f64| GCodePosition {
+            x,
+            y,
+            z: from.z + dz * t,
+        };
+        let mut points: Vec<GCodePosition> = vec![];
+        if let Some(arc) = self.arc {
+            let tolerance = tolerance.min(arc.radius);
+            let chord = (tolerance * (2.0 * arc.radius - tolerance)).sqrt();
+            let count = if chord > 0.0 {
+                (0.5 * arc.length() / chord).floor().max(1.0) as usize
+            } else {
+                1
+            };
+            for i in 1..count {
+                points.push(self.arc_position(&arc, i as f64 / count as f64));
+            }
+        }
+        let mut push = |line: &LineSegment<f64>, t: std::ops::Range<f64>| {
+            points.push(position(line.to.x, line.to.y, t.end));
+        };
+        match self.spline {
+            Some(GCodeSpline::Cubic { ctrl1, ctrl2 }) => CubicBezierSegment {
+                from: point(from.x, from.y),
+                ctrl1: point(ctrl1.0, ctrl1.1),
+                ctrl2: point(ctrl2.0, ctrl2.1),
+                to: point(to.x, to.y),
+            }
+            .for_each_flattened_with_t(tolerance, &mut push),
+            Some(GCodeSpline::Quadratic { ctrl }) => QuadraticBezierSegment {
+                from: point(from.x, from.y),
+                ctrl: point(ctrl.0, ctrl.1),
+                to: point(to.x, to.y),
+            }
+            .for_each_flattened_with_t(tolerance, &mut push),
+            None => {}
+        }
+        //保证终点准确
+        points.pop_if(|p| *p == to);
+        points.push(to);
+        points
+    }
+
+    /// 挤出状态
+    pub fn extrusion_state(&self) -> GCodeExtrusionState {
+        GCodeExtrusionState::from_extrusion(self.extrusion)
+    }
+
+    /// 平移运动, 用来在机床坐标和工件坐标之间转换
+    /// - [offset] 需要减去的偏移
+    pub fn translate(&self, offset: &GCodePosition) -> GCodeMotion {
+        GCodeMotion {
+            from: self.from - *offset,
+            to: self.to - *offset,
+            arc: self.arc.map(|arc| {
+                let (ox, oy, _) = plane_axes(arc.plane, offset);
+                GCodeArc {
+                    cx: arc.cx - ox,
+                    cy: arc.cy - oy,
+                    ..arc
+                }
+            }),
+            spline: self.spline.map(|spline| {
+                let translate = |(x, y): (f64, f64)| (x - offset.x, y - offset.y);
+                match spline {
+                    GCodeSpline::Cubic { ctrl1, ctrl2 } => GCodeSpline::Cubic {
+                        ctrl1: translate(ctrl1),
+                        ctrl2: translate(ctrl2),
+                    },
+                    GCodeSpline::Quadratic { ctrl } => GCodeSpline::Quadratic {
+                        ctrl: translate(ctrl),
+                    },
+                }
+            }),
+            ..self.clone()
+        }
+    }
+}
+
+impl GCodeExtrusionState {
+    /// 根据挤出的长度判断状态
+    pub fn from_extrusion(extrusion: f64) -> Self {
+        if extrusion > 0.0 {
+            GCodeExtrusionState::Extrude
+        } else if extrusion < 0.0 {
+            GCodeExtrusionState::Retract
+        } else {
+            GCodeExtrusionState::Travel
+        }
+    }
+}
+
+impl GCodeArc {
+    /// 圆弧的长度
+    pub fn length(&self) -> f64 {
+        self.radius * self.sweep_angle.abs()
+    }
+}
+
+impl GCodeModalState {
+    /// 当前单位转换成mm的缩放系数
+    /// - 1inch = 25.4mm
+    pub fn unit_scale(&self) -> f64 {
+        match self.units {
+            GCodeUnits::Inches => 25.4,
+            GCodeUnits::Millimeters => 1.0,
+        }
+    }
+
+    /// 当前的进给速度, 单位mm/min
+    /// - 只有[GCodeFeedMode::UnitsPerMinute]模式才会转换单位
+    pub fn feed_rate_mm(&self) -> f64 {
+        match self.feed_mode {
+            GCodeFeedMode::UnitsPerMinute => self.feed_rate * self.unit_scale(),
+            _ => self.feed_rate,
+        }
+    }
+
+    /// 主轴/激光是否打开
+    pub fn is_spindle_on(&self) -> bool {
+        self.spindle != GCodeSpindleState::Off
+    }
+
+    /// 当前的运动是否是切割
+    /// - [laser_mode] 激光模式, 只有激光打开并且功率>0时的运动才是切割, 否则只有`G0`是空走
+    /// - [rapid_laser_off] 激光模式下`G0`是否总是关闭激光, 否则只有`M3`恒定功率模式下的`G0`会切割
+    pub fn is_cut(&self, mode: GCodeMotionMode, laser_mode: bool, rapid_laser_off: bool) -> bool {
+        if !laser_mode {
+            return mode != GCodeMotionMode::Rapid;
+        }
+        let laser_on = self.is_spindle_on() && self.spindle_speed > 0.0;
+        match mode {
+            GCodeMotionMode::Rapid => {
+                laser_on && !rapid_laser_off && self.spindle == GCodeSpindleState::Clockwise
+            }
+            _ => laser_on,
+        }
+    }
+
+    /// 当前工件坐标原点的机床坐标, 包含`G92`的偏移
+    pub fn work_offset(&self) -> GCodePosition {
+        self.work_offsets[self.coordinate_system] + self.position_offset
+    }
+
+    /// 当前位置的工件坐标
+    pub fn work_position(&self) -> GCodePosition {
+        self.position - self.work_offset()
+    }
+
+    /// 当前是否是相对坐标
+    pub fn is_relative(&self) -> bool {
+        self.distance_mode == GCodeDistanceMode::Relative
+    }
+
+    /// 应用一行[GCodeValue]
+    pub fn apply_values(&mut self, values: &[GCodeValue]) -> Option<GCodeMotion> {
+        self.apply(&GCodeCommand::from_values(values))
+    }
+
+    /// 展开固定循环, 返回需要依次[apply]的每一行指令
+    /// - 固定循环的行会展开成`G0`/`G1`/`G4`等明确的运动, 其他的行原样返回
+    /// - 展开的运动在[apply]时会记录所属的循环[GCodeMotion::cycle]
+    pub fn expand_cycle(&mut self, commands: &[GCodeCommand]) -> Vec<Vec<GCodeCommand>> {
+        let mut cycle = std::mem::take(&mut self.cycle);
+        let lines = cycle.expand(self, commands);
+        self.cycle = cycle;
+        lines
+    }
+
+    /// 应用一行指令, 更新模态状态
+    /// - 同一行中的模态指令会先于运动指令生效, 比如`G1 X1 G91`
+    /// - 返回这一行的运动, 只有坐标的行使用当前的运动模式
+    pub fn apply(&mut self, commands: &[GCodeCommand]) -> Option<GCodeMotion> {
+        let mut motion: Option<(GCodeMotionMode, &GCodeParams)> = None;
+        //`G53`
+        let mut machine = false;
+        //`G28`
+        let mut home: Option<&GCodeParams> = None;
+        for command in commands {
+            match command {
+                GCodeCommand::Rapid(params) => {
+                    self.motion_mode = GCodeMotionMode::Rapid;
+                    motion = Some((self.motion_mode, params));
+                }
+                GCodeCommand::Linear(params) => {
+                    self.motion_mode = GCodeMotionMode::Linear;
+                    motion = Some((self.motion_mode, params));
+                }
+                GCodeCommand::Arc { clockwise, params } => {
+                    self.motion_mode = if *clockwise {
+                        GCodeMotionMode::ArcCw
+                    } else {
+                        GCodeMotionMode::ArcCcw
+                    };
+                    motion = Some((self.motion_mode, params));
+                }
+                GCodeCommand::CubicSpline(params) => {
+                    self.motion_mode = GCodeMotionMode::CubicSpline;
+                    motion = Some((self.motion_mode, params));
+                }
+                GCodeCommand::QuadraticSpline(params) => {
+                    self.motion_mode = GCodeMotionMode::QuadraticSpline;
+                    motion = Some((self.motion_mode, params));
+                }
+                GCodeCommand::Plane(plane) => self.plane = *plane,
+                GCodeCommand::Units(units) => self.units = *units,
+                GCodeCommand::DistanceMode(mode) => {
+                    self.distance_mode = *mode;
+                    self.extrusion_mode = *mode;
+                }
+                GCodeCommand::ExtrusionMode(mode) => self.extrusion_mode = *mode,
+                GCodeCommand::FeedMode(mode) => self.feed_mode = *mode,
+                //没有展开的固定循环不会运动, 参考[expand_cycle]
+                GCodeCommand::CancelMotion | GCodeCommand::Cycle { .. } => {
+                    self.motion_mode = GCodeMotionMode::Cancel
+                }
+                GCodeCommand::MachineCoordinates => machine = true,
+                GCodeCommand::CoordinateSystem(index) if *index < WORK_COORDINATE_COUNT => {
+                    self.coordinate_system = *index
+                }
+                GCodeCommand::SetPosition(params) => self.set_position(params),
+                GCodeCommand::ResetPosition => self.position_offset = GCodePosition::default(),
+                GCodeCommand::Home(params) => home = Some(params),
+                GCodeCommand::SpindleOn { clockwise, s } => {
+                    self.spindle = if *clockwise {
+                        GCodeSpindleState::Clockwise
+                    } else {
+                        GCodeSpindleState::CounterClockwise
+                    };
+                    if let Some(s) = s {
+                        self.spindle_speed = *s;
+                    }
+                }
+                GCodeCommand::SpindleOff => self.spindle = GCodeSpindleState::Off,
+                GCodeCommand::ToolSelect(tool) | GCodeCommand::ToolChange(Some(tool)) => {
+                    self.tool = *tool
+                }
+                GCodeCommand::Modal(params) => {
+                    if let Some(f) = params.f {
+                        self.feed_rate = f;
+                    }
+                    if let Some(s) = params.s {
+                        self.spindle_speed = s;
+                    }
+                    if params.have_axis() {
+                        motion = Some((self.motion_mode, params));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(params) = home {
+            self.spline_ctrl = None;
+            return Some(self.home(params));
+        }
+
+        let Some((mode, params)) = motion else {
+            self.spline_ctrl = None;
+            return None;
+        };
+        if let Some(f) = params.f {
+            self.feed_rate = f;
+        }
+        if let Some(s) = params.s {
+            self.spindle_speed = s;
+        }
+        let is_arc = matches!(mode, GCodeMotionMode::ArcCw | GCodeMotionMode::ArcCcw);
+        let have_center =
+            params.i.is_some() || params.j.is_some() || params.k.is_some() || params.r.is_some();
+        if mode == GCodeMotionMode::Cancel || !(params.have_axis() || (is_arc && have_center)) {
+            self.spline_ctrl = None;
+            return None;
+        }
+
+        let from = self.position;
+        let to = self.target_position(params, machine);
+        let extrusion = match params.e {
+            Some(e) if self.extrusion_mode == GCodeDistanceMode::Relative => e * self.unit_scale(),
+            Some(e) => e * self.unit_scale() - self.e,
+            None => 0.0,
+        };
+        self.e += extrusion;
+        let spline = if self.plane == GCodePlane::XY {
+            self.spline(mode, &from, &to, params)
+        } else {
+            None
+        };
+        self.spline_ctrl = match spline {
+            Some(GCodeSpline::Cubic { ctrl2, .. }) => Some(ctrl2),
+            _ => None,
+        };
+        let arc = if is_arc {
+            self.arc(mode == GCodeMotionMode::ArcCw, &from, &to, params)
+        } else {
+            None
+        };
+        self.position = to;
+        Some(GCodeMotion {
+            mode,
+            from,
+            to,
+            arc,
+            spline,
+            extrusion,
+            cycle: self.cycle.cycle,
+            params: params.clone(),
+        })
+    }
+
+    /// 计算目标位置的机床坐标, 没有的坐标使用当前的位置
+    /// - [machine] 绝对坐标是否是机床坐标`G53`
+    fn target_position(&self, params: &GCodeParams, machine: bool) -> GCodePosition {
+        let scale = self.unit_scale();
+        let relative = self.is_relative();
+        let offset = if machine {
+            GCodePosition::default()
+        } else {
+            self.work_offset()
+        };
+        let axis = |value: Option<f64>, current: f64, offset: f64| match value {
+            Some(value) if relative => current + value * scale,
+            Some(value) => value * scale + offset,
+            None => current,
+        };
+        GCodePosition {
+            x: axis(params.x, self.position.x, offset.x),
+            y: axis(params.y, self.position.y, offset.y),
+            z: axis(params.z, self.position.z, offset.z),
+        }
+    }
+
+    /// `G92` 修改偏移, 使当前位置的工件坐标等于参数
+    /// - `E`直接修改挤出轴的位置
+    fn set_position(&mut self, params: &GCodeParams) {
+        let scale = self.unit_scale();
+        let origin = self.work_offsets[self.coordinate_system];
+        let offset = |value: Option<f64>, position: f64, origin: f64, offset: f64| match value {
+            Some(value) => position - origin - value * scale,
+            None => offset,
+        };
+        self.position_offset = GCodePosition {
+            x: offset(params.x, self.position.x, origin.x, self.position_offset.x),
+            y: offset(params.y, self.position.y, origin.y, self.position_offset.y),
+            z: offset(params.z, self.position.z, origin.z, self.position_offset.z),
+        };
+        if let Some(e) = params.e {
+            self.e = e * scale;
+        }
+    }
+
+    /// `G28` 快速移动到原点
+    /// - 有坐标轴参数时只有对应的轴回原点, 否则所有的轴都回原点
+    /// - 参数中的中间点会被忽略, 返回运动的参数为回到原点的轴
+    fn home(&mut self, params: &GCodeParams) -> GCodeMotion {
+        let all = params.x.is_none() && params.y.is_none() && params.z.is_none();
+        let axis = |value: Option<f64>, home: f64| (all || value.is_some()).then_some(home);
+        let home = GCodeParams {
+            x: axis(params.x, self.home_position.x),
+            y: axis(params.y, self.home_position.y),
+            z: axis(params.z, self.home_position.z),
+            ..Default::default()
+        };
+        let from = self.position;
+        let to = GCodePosition {
+            x: home.x.unwrap_or(from.x),
+            y: home.y.unwrap_or(from.y),
+            z: home.z.unwrap_or(from.z),
+        };
+        self.position = to;
+        GCodeMotion {
+            mode: GCodeMotionMode::Rapid,
+            from,
+            to,
+            arc: None,
+            spline: None,
+            extrusion: 0.0,
+            cycle: None,
+            params: home,
+        }
+    }
+
+    /// 计算XY平面的样条
+    /// - 没有`I`/`J`的`G5`, 第一个控制点为上一段`G5`第二个控制点关于起点的对称点
+    fn spline(
+        &self,
+        mode: GCodeMotionMode,
+        from: &GCodePosition,
+        to: &GCodePosition,
+        params: &GCodeParams,
+    ) -> Option<GCodeSpline> {
+        let scale = self.unit_scale();
+        let offset = |base: &GCodePosition, x: Option<f64>, y: Option<f64>| {
+            (
+                base.x + x.unwrap_or(0.0) * scale,
+                base.y + y.unwrap_or(0.0) * scale,
+            )
+        };
+        match mode {
+            GCodeMotionMode::CubicSpline => {
+                let ctrl1 = match (params.i.or(params.j), self.spline_ctrl) {
+                    (None, Some((x, y))) => (2.0 * from.x - x, 2.0 * from.y - y),
+                    _ => offset(from, params.i, params.j),
+                };
+                let ctrl2 = offset(to, params.p, params.q);
+                Some(GCodeSpline::Cubic { ctrl1, ctrl2 })
+            }
+            GCodeMotionMode::QuadraticSpline => Some(GCodeSpline::Quadratic {
+                ctrl: offset(from, params.i, params.j),
+            }),
+            _ => None,
+        }
+    }
+
+    /// 计算当前平面的圆弧
+    /// - `I`/`J`/`K` 圆心相对于起点的偏移, XY平面为`I`/`J`, ZX平面为`K`/`I`, YZ平面为`J`/`K`
+    /// - `R` 圆弧半径, 负数表示大于180°的圆弧
+    /// - `P` 圆弧的圈数
+    /// - 起点和终点相同时为整圆, `R`格式不支持整圆
+    fn arc(
+        &self,
+        clockwise: bool,
+        from: &GCodePosition,
+        to: &GCodePosition,
+        params: &GCodeParams,
+    ) -> Option<GCodeArc> {
+        let scale = self.unit_scale();
+        let plane = self.plane;
+        let (sx, sy, _) = plane_axes(plane, from);
+        let (x, y, _) = plane_axes(plane, to);
+        let (i, j) = match plane {
+            GCodePlane::XY => (params.i, params.j),
+            GCodePlane::ZX => (params.k, params.i),
+            GCodePlane::YZ => (params.j, params.k),
+        };
+        let is_full_circle = (x - sx).hypot(y - sy) < ARC_EPSILON;
+
+        //圆心
+        let (cx, cy) = if let Some(r) = params.r {
+            if is_full_circle {
+                return None;
+            }
+            let r = r * scale;
+            let (dx, dy) = (x - sx, y - sy);
+            let d = dx.hypot(dy);
+            let h = (r * r - d * d / 4.0).max(0.0).sqrt();
+            //顺时针小圆弧的圆心在前进方向的右侧
+            let sign = if clockwise == (r > 0.0) { -1.0 } else { 1.0 };
+            (
+                sx + dx / 2.0 - sign * h * dy / d,
+                sy + dy / 2.0 + sign * h * dx / d,
+            )
+        } else {
+            (sx + i.unwrap_or(0.0) * scale, sy + j.unwrap_or(0.0) * scale)
+        };
+
+        let radius = (sx - cx).hypot(sy - cy);
+        if radius < ARC_EPSILON {
+            return None;
+        }
+        let start_angle = (sy - cy).atan2(sx - cx);
+        let mut sweep_angle = if is_full_circle {
+            0.0
+        } else {
+            (y - cy).atan2(x - cx) - start_angle
+        };
+        if clockwise {
+            if sweep_angle >= 0.0 {
+                sweep_angle -= TAU;
+            }
+        } else if sweep_angle <= 0.0 {
+            sweep_angle += TAU;
+        }
+        //多圈
+        if let Some(turns) = params.p {
+            let turns = turns.floor() - 1.0;
+            if turns > 0.0 {
+                sweep_angle += sweep_angle.signum() * TAU * turns;
+            }
+        }
+        Some(GCodeArc {
+            plane,
+            cx,
+            cy,
+            radius,
+            start_angle,
+            sweep_angle,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::{GCodeCommand, GCodeDistanceMode, GCodePlane, GCodeUnits};
+    use crate::handler::{GCodeFlow, GCodeValueHandler};
+    use crate::modal::{
+        GCodeModalState, GCodeMotion, GCodeMotionMode, GCodePosition, GCodeSpindleState,
+    };
+    use crate::parser::GCodeValue;
+    use crate::parser::{GCodeLine, GCodeParser};
+    use std::f64::consts::PI;
+
+    /// 收集所有运动的终点
+    #[derive(Default)]
+    struct MotionHandler {
+        modal: GCodeModalState,
+        points: Vec<(GCodeMotionMode, GCodePosition)>,
+        /// 最后一个运动
+        last: Option<GCodeMotion>,
+    }
+
+    impl GCodeValueHandler for MotionHandler {
+        fn handle_gcode_line(&mut self, gcode_line: GCodeLine) -> GCodeFlow {
+            if let Some(motion) = self.modal.apply(&gcode_line.commands()) {
+                self.points.push((motion.mode, motion.to));
+                self.last = Some(motion);
+            }
+            GCodeFlow::Continue
+        }
+
+        fn handle_gcode_value(&mut self, _gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+            GCodeFlow::Continue
+        }
+    }
+
+    fn position(x: f64, y: f64, z: f64) -> GCodePosition {
+        GCodePosition { x, y, z }
+    }
+
+    #[test]
+    fn test_gcode_modal_state() {
+        let gcode =
+            "G1 X1 Y1 F100\nX2\nY2 G91\nG20 X1 Z1\nG80\nX5\nG0 X0 G90 G21\nF200".to_string();
+        let mut handler = MotionHandler::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_eq!(
+            handler.points,
+            vec![
+                (GCodeMotionMode::Linear, position(1.0, 1.0, 0.0)),
+                (GCodeMotionMode::Linear, position(2.0, 1.0, 0.0)),
+                (GCodeMotionMode::Linear, position(2.0, 3.0, 0.0)),
+                (GCodeMotionMode::Linear, position(27.4, 3.0, 25.4)),
+                (GCodeMotionMode::Rapid, position(0.0, 3.0, 25.4)),
+            ]
+        );
+        let modal = &handler.modal;
+        assert_eq!(modal.motion_mode, GCodeMotionMode::Rapid);
+        assert_eq!(modal.distance_mode, GCodeDistanceMode::Absolute);
+        assert_eq!(modal.units, GCodeUnits::Millimeters);
+        assert_eq!(modal.feed_rate, 200.0);
+    }
+
+    #[test]
+    fn test_gcode_modal_coordinates() {
+        let gcode = "G55\nG0 X1 Y1\nG92 X0 Y0\nG1 X2\nG53 G0 X0 Y0\nG92.1\nG54 G0 X5 Y5 Z1\nG28 X"
+            .to_string();
+        let mut handler = MotionHandler::default();
+        handler.modal.work_offsets[1] = position(10.0, 20.0, 0.0);
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_eq!(
+            handler.points,
+            vec![
+                (GCodeMotionMode::Rapid, position(11.0, 21.0, 0.0)),
+                (GCodeMotionMode::Linear, position(13.0, 21.0, 0.0)),
+                (GCodeMotionMode::Rapid, position(0.0, 0.0, 0.0)),
+                (GCodeMotionMode::Rapid, position(5.0, 5.0, 1.0)),
+                (GCodeMotionMode::Rapid, position(0.0, 5.0, 1.0)),
+            ]
+        );
+        let modal = &handler.modal;
+        assert_eq!(modal.coordinate_system, 0);
+        assert_eq!(modal.position_offset, GCodePosition::default());
+        assert_eq!(modal.work_position(), position(0.0, 5.0, 1.0));
+        assert_eq!(modal.motion_mode, GCodeMotionMode::Rapid);
+    }
+
+    #[test]
+    fn test_gcode_modal_spindle() {
+        let gcode = "G20 F10\nT2\nM4 S300\nG1 X1 S500".to_string();
+        let mut handler = MotionHandler::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let modal = &mut handler.modal;
+        assert_eq!(modal.tool, 2);
+        assert_eq!(modal.spindle, GCodeSpindleState::CounterClockwise);
+        assert_eq!(modal.spindle_speed, 500.0);
+        assert_eq!(modal.feed_rate_mm(), 254.0);
+        assert!(modal.is_spindle_on());
+        modal.apply(&[GCodeCommand::SpindleOff]);
+        assert!(!modal.is_spindle_on());
+    }
+    /// 解析每一行, 返回最后一个运动
+    fn last_motion(gcode: &str) -> GCodeMotion {
+        let mut handler = MotionHandler::default();
+        GCodeParser::new(&gcode.to_string()).parse(&mut handler);
+        handler.last.unwrap()
+    }
+
+    #[track_caller]
+    fn assert_position(a: GCodePosition, b: GCodePosition) {
+        assert!(
+            (a.x - b.x).abs() < 1e-9 && (a.y - b.y).abs() < 1e-9 && (a.z - b.z).abs() < 1e-9,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn test_gcode_modal_arc_plane() {
+        //螺旋线, Z线性移动
+        let motion = last_motion("G0 X10 Y0 Z0\nG3 X-10 Y0 Z-2 I-10 J0");
+        let arc = motion.arc.unwrap();
+        assert_eq!(arc.plane, GCodePlane::XY);
+        assert_position(motion.arc_position(&arc, 0.5), position(0.0, 10.0, -1.0));
+        assert!((motion.length() - (PI * 10.0).hypot(2.0)).abs() < 1e-9);
+        for p in motion.flatten(0.01) {
+            assert!((p.z + 2.0 * p.y.atan2(p.x) / PI).abs() < 1e-9, "{:?}", p);
+        }
+
+        //G18 ZX平面, 圆心偏移为K/I
+        let motion = last_motion("G18\nG0 X10 Y0 Z0\nG2 X-10 Y4 I-10 K0");
+        let arc = motion.arc.unwrap();
+        assert_eq!(arc.plane, GCodePlane::ZX);
+        assert_position(motion.arc_position(&arc, 0.5), position(0.0, 2.0, 10.0));
+        assert!((motion.length() - (PI * 10.0).hypot(4.0)).abs() < 1e-9);
+
+        //G19 YZ平面, 圆心偏移为J/K
+        let motion = last_motion("G19\nG0 X0 Y10 Z0\nG3 Y0 Z10 J-10 K0");
+        let arc = motion.arc.unwrap();
+        assert_eq!(arc.plane, GCodePlane::YZ);
+        let half = (0.25 * PI).cos() * 10.0;
+        assert_position(motion.arc_position(&arc, 0.5), position(0.0, half, half));
+        let points = motion.flatten(0.01);
+        assert!(
+            points
+                .iter()
+                .all(|p| ((p.y).hypot(p.z) - 10.0).abs() < 0.011)
+        );
+    }
+}
@@ -1,5 +1,8 @@
-use crate::handler::GCodeValueHandler;
-use std::str::Chars;
+use crate::command::GCodeCommand;
+use crate::diagnostic::{GCodeDiagnostic, GCodeDiagnosticKind, GCodeParseError, GCodeValidator};
+use crate::handler::{GCodeFlow, GCodeValueHandler};
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind};
 
 ///
 /// @author <a href="mailto: angcyo@126.com">angcyo</a>
@@ -9,20 +12,89 @@ use std::str::Chars;
 pub struct GCodeParser<'a> {
     /// GCode 数据
     gcode: &'a String,
+    /// 是否打开了跳段开关(Block Delete)
+    /// - 打开后, `/`开头的行会被忽略
+    pub block_delete: bool,
+    /// [GCodeParser::try_parse]时使用的校验器
+    pub validator: GCodeValidator,
 }
 
 /// GCode 数值部分
 /// - G90
 /// - G21
 /// - G0 / G1 / G2 / G3
-/// - S / F / M
-/// - X / Y / I / J
-#[derive(Clone, Debug)]
+/// - S / F / M / T / N
+/// - X / Y / Z / A / B / C / E
+/// - I / J / K / P / Q / R
+/// - `*` 校验和
+///
+/// GCode中的数字不支持科学计数法, 所以`E`始终是一个指令(挤出轴)
+#[derive(Clone, Debug, PartialEq)]
 pub struct GCodeValue {
     /// 指令
     pub command: String,
     /// 数值
     pub value: String,
+    /// 在源数据中的位置
+    pub span: GCodeSpan,
+}
+
+/// 数据在源数据中的位置
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GCodeSpan {
+    /// 行号, 从1开始
+    pub line: usize,
+    /// 相对于源数据开始的字节偏移量
+    pub offset: usize,
+    /// 字节长度
+    pub len: usize,
+}
+
+/// 解析出来的一行GCode数据
+#[derive(Clone, Debug, Default)]
+pub struct GCodeLine {
+    /// 整行在源数据中的位置, 不包含换行符
+    pub span: GCodeSpan,
+    /// 整行原始文本
+    pub text: String,
+    /// 行中有效的[GCodeValue]
+    pub values: Vec<GCodeValue>,
+    /// 行中的注释, `;`注释和`()`注释
+    pub comments: Vec<GCodeComment>,
+    /// 是否是`/`开头的跳段行(Block Delete)
+    pub block_delete: bool,
+    /// 是否是`%`程序开始/结束标记行
+    pub program_marker: bool,
+}
+
+/// GCode中的注释
+/// - `; comment`
+/// - `(comment)`
+#[derive(Clone, Debug)]
+pub struct GCodeComment {
+    /// 注释内容, 不包含`;`和`()`, 并且去掉了首尾空白
+    pub text: String,
+    /// 是否是`()`括号注释
+    pub is_paren: bool,
+    /// 注释在源数据中的位置, 包含`;`和`()`
+    pub span: GCodeSpan,
+}
+
+impl GCodeLine {
+    /// 解析成类型化的指令
+    pub fn commands(&self) -> Vec<GCodeCommand> {
+        GCodeCommand::from_values(&self.values)
+    }
+}
+
+impl GCodeComment {
+    fn new(text: &str, is_paren: bool, line: usize, offset: usize, len: usize) -> Self {
+        Self {
+            text: text.trim().to_string(),
+            is_paren,
+            span: GCodeSpan { line, offset, len },
+        }
+    }
 }
 
 impl GCodeValue {
@@ -30,6 +102,7 @@ impl GCodeValue {
         Self {
             command: "".to_string(),
             value: "".to_string(),
+            span: GCodeSpan::default(),
         }
     }
 
@@ -48,6 +121,41 @@ impl GCodeValue {
     pub fn is_y(&self) -> bool {
         self.command == "Y"
     }
+    pub fn is_z(&self) -> bool {
+        self.command == "Z"
+    }
+    pub fn is_e(&self) -> bool {
+        self.command == "E"
+    }
+
+    /// 是否是`*`校验和
+    pub fn is_checksum(&self) -> bool {
+        self.command == "*"
+    }
+
+    /// 是否是未知的指令, 既不是字母地址也不是校验和
+    /// - 比如 `#1=2` 中的 `#` / `=`
+    pub fn is_unknown(&self) -> bool {
+        !self.is_checksum() && !self.command.chars().all(is_word_letter)
+    }
+
+    /// 解析数值, 数值格式错误时返回诊断信息
+    /// - 只支持`[+-]数字[.数字]`格式, 比如`X1.2.3` / `Y--5` / 缺少数值的`X`都是错误的
+    pub fn try_value_f64(&self) -> Result<f64, GCodeDiagnostic> {
+        let digits = self.value.strip_prefix(['+', '-']).unwrap_or(&self.value);
+        let is_number = !digits.is_empty()
+            && digits != "."
+            && digits.matches('.').count() <= 1
+            && digits.chars().all(|c| c.is_ascii_digit() || c == '.');
+        match self.value.parse::<f64>() {
+            Ok(value) if is_number => Ok(value),
+            _ => Err(GCodeDiagnostic::new(
+                GCodeDiagnosticKind::MalformedNumber,
+                format!("malformed number `{}`", self.to_string()),
+                self.span,
+            )),
+        }
+    }
 
     /// 数值
     pub fn value_f32(&self) -> f32 {
@@ -60,6 +168,12 @@ impl GCodeValue {
     }
 }
 
+/// 是否是GCode字母地址
+/// - RS-274/Marlin/GRBL 中 `A`~`Z` 都是有效的地址字母
+pub fn is_word_letter(c: char) -> bool {
+    c.is_ascii_alphabetic()
+}
+
 /// 实现[Display]
 impl std::fmt::Display for GCodeValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -69,79 +183,507 @@ impl std::fmt::Display for GCodeValue {
 
 impl<'a> GCodeParser<'a> {
     pub fn new(gcode: &'a String) -> Self {
-        Self { gcode }
+        Self {
+            gcode,
+            block_delete: false,
+            validator: GCodeValidator::default(),
+        }
     }
 
     /// 开始解析
+    /// - [handler]返回[GCodeFlow::Abort]时, 中断解析
     pub fn parse(&mut self, handler: &mut impl GCodeValueHandler) {
-        let mut chars = self.gcode.chars();
+        //内存数据读取不会出错
+        let _ = self._stream_parser().parse(handler);
+    }
+
+    /// 校验并解析
+    /// - 校验未通过的行不会交给[handler]处理
+    /// - [GCodeValidator::strict]严格模式下, 遇到第一个错误就中断解析
+    /// - 返回所有的诊断信息
+    pub fn try_parse(
+        &mut self,
+        handler: &mut impl GCodeValueHandler,
+    ) -> Result<(), Vec<GCodeDiagnostic>> {
+        match self._stream_parser().try_parse(handler) {
+            Ok(_) => Ok(()),
+            Err(GCodeParseError::Invalid(diagnostics)) => Err(diagnostics),
+            //内存数据读取不会出错
+            Err(GCodeParseError::Io(_)) => Ok(()),
+        }
+    }
+
+    fn _stream_parser(&self) -> GCodeStreamParser<&'a [u8]> {
+        let mut parser = GCodeStreamParser::new(self.gcode.as_bytes());
+        parser.block_delete = self.block_delete;
+        parser.validator = self.validator.clone();
+        parser
+    }
+}
+
+/// 流式解析 GCode 数据
+/// - 支持任意[BufRead]数据源, 比如文件/网络/解压流
+/// - 每次只读取一行数据, 内存占用只和最长的一行有关
+/// - `\n`/`\r\n`/`\r`都是换行
+pub struct GCodeStreamParser<R: BufRead> {
+    /// 数据源
+    reader: R,
+    /// 上一行是否以`\r`结束, 之后紧跟的`\n`属于上一行
+    is_pending_cr: bool,
+    /// 是否打开了跳段开关(Block Delete)
+    /// - 打开后, `/`开头的行会被忽略
+    pub block_delete: bool,
+    /// [GCodeStreamParser::try_parse]时使用的校验器
+    pub validator: GCodeValidator,
+}
+
+impl GCodeStreamParser<BufReader<File>> {
+    /// 打开一个GCode文件进行流式解析
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> GCodeStreamParser<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            is_pending_cr: false,
+            block_delete: false,
+            validator: GCodeValidator::default(),
+        }
+    }
+
+    /// 开始解析
+    /// - 读取数据源出错时, 中断解析并返回错误
+    /// - [handler]返回[GCodeFlow::Abort]时, 中断解析
+    pub fn parse(&mut self, handler: &mut impl GCodeValueHandler) -> std::io::Result<()> {
+        self._parse(handler, None)
+    }
+
+    /// 校验并解析
+    /// - 校验未通过的行不会交给[handler]处理
+    /// - [GCodeValidator::strict]严格模式下, 遇到第一个错误就中断解析
+    pub fn try_parse(
+        &mut self,
+        handler: &mut impl GCodeValueHandler,
+    ) -> Result<(), GCodeParseError> {
+        let mut diagnostics = vec![];
+        self._parse(handler, Some(&mut diagnostics))?;
+        if diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(GCodeParseError::Invalid(diagnostics))
+        }
+    }
+
+    /// 解析数据
+    /// - [diagnostics] 不为空时, 校验每一行数据, 并收集诊断信息
+    fn _parse(
+        &mut self,
+        handler: &mut impl GCodeValueHandler,
+        mut diagnostics: Option<&mut Vec<GCodeDiagnostic>>,
+    ) -> std::io::Result<()> {
+        let mut buffer: Vec<u8> = Vec::new();
+        //当前行号
+        let mut line_number = 0;
+        //当前读取到的字节偏移量
+        let mut offset = 0;
         handler.start();
-        loop {
-            let (line, is_end) = self._read_gcode_value_line(&mut chars);
-            if !line.is_empty() {
-                handler.handle_gcode_value(line);
-            }
-            if is_end {
+        'read: loop {
+            buffer.clear();
+            let Some((skip, read)) = self._read_line(&mut buffer)? else {
                 break;
+            };
+            offset += skip;
+            line_number += 1;
+            let line = self._read_gcode_line(&buffer, line_number, offset);
+            offset += read;
+            if line.block_delete && self.block_delete {
+                //跳过`/`开头的行
+                continue;
+            }
+            if let Some(diagnostics) = diagnostics.as_mut() {
+                let line_diagnostics = self.validator.validate(&line);
+                if let Some(first) = line_diagnostics.first() {
+                    if self.validator.strict {
+                        diagnostics.push(first.clone());
+                        break 'read;
+                    }
+                    diagnostics.extend(line_diagnostics);
+                    continue;
+                }
+            }
+            for comment in line.comments.iter() {
+                handler.handle_comment(comment);
+            }
+            if (!line.values.is_empty() || line.program_marker)
+                && handler.handle_gcode_line(line) == GCodeFlow::Abort
+            {
+                break 'read;
             }
         }
         handler.end();
+        Ok(())
     }
 
-    /// 读取一行中有效的[GCodeValue]
-    fn _read_gcode_value_line(&self, chars: &mut Chars) -> (Vec<GCodeValue>, bool) {
-        let mut values: Vec<GCodeValue> = Vec::new();
+    /// 读取一行数据到[buffer], 不包含换行符
+    /// - 同时查找`\r`和`\n`, 只使用`\r`换行的数据也不会一次读取整个文件
+    /// - 返回行首之前跳过的`\n`字节数, 和这一行读取的字节数(包含换行符)
+    /// - 返回[None]表示读取完毕
+    fn _read_line(&mut self, buffer: &mut Vec<u8>) -> std::io::Result<Option<(usize, usize)>> {
+        let mut skip = 0;
+        let mut read = 0;
+        loop {
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            if available.is_empty() {
+                return Ok(if read == 0 { None } else { Some((skip, read)) });
+            }
+            if self.is_pending_cr {
+                //`\r\n`
+                self.is_pending_cr = false;
+                if available[0] == b'\n' {
+                    self.reader.consume(1);
+                    skip = 1;
+                    continue;
+                }
+            }
+            match available.iter().position(|b| *b == b'\n' || *b == b'\r') {
+                Some(index) => {
+                    buffer.extend_from_slice(&available[..index]);
+                    self.is_pending_cr = available[index] == b'\r';
+                    self.reader.consume(index + 1);
+                    return Ok(Some((skip, read + index + 1)));
+                }
+                None => {
+                    let len = available.len();
+                    buffer.extend_from_slice(available);
+                    self.reader.consume(len);
+                    read += len;
+                }
+            }
+        }
+    }
+
+    /// 读取一行数据
+    /// - [line_number] 行号
+    /// - [offset] 行首的字节偏移量
+    fn _read_gcode_line(&self, bytes: &[u8], line_number: usize, offset: usize) -> GCodeLine {
+        let text = GCodeLossyText::new(bytes);
+        let mut line = GCodeLine {
+            span: GCodeSpan {
+                line: line_number,
+                offset,
+                len: bytes.len(),
+            },
+            text: "".to_string(),
+            values: vec![],
+            comments: vec![],
+            block_delete: false,
+            program_marker: false,
+        };
+        self._read_gcode_value_line(&text, &mut line);
+        line.text = text.text;
+        line
+    }
+
+    /// 读取一行中有效的[GCodeValue]和注释
+    fn _read_gcode_value_line(&self, lossy: &GCodeLossyText, line: &mut GCodeLine) {
+        let line_number = line.span.line;
+        let offset = line.span.offset;
+        //文本中的位置对应源数据中的字节偏移量
+        let pos = |index: usize| offset + lossy.raw_index(index);
+        let text = lossy.text.as_str();
         let mut value = GCodeValue::new();
-        while let Some(c) = chars.next() {
-            match c {
-                //有效指令
-                'G' | 'M' | 'X' | 'Y' | 'I' | 'J' | 'S' | 'F' | 'Z' => {
-                    if !value.command.is_empty() {
-                        // 处理上一个指令
-                        values.push(value);
-                        value = GCodeValue::new();
-                    }
-                    value.command.push(c);
+        //是否还在行首, 用来识别`/`和`%`
+        let mut is_line_start = true;
+        //`(`注释开始的位置
+        let mut paren_start: Option<usize> = None;
+        for (index, c) in text.char_indices() {
+            if let Some(start) = paren_start {
+                if c == ')' {
+                    line.comments.push(GCodeComment::new(
+                        &text[start + 1..index],
+                        true,
+                        line_number,
+                        pos(start),
+                        pos(index + 1) - pos(start),
+                    ));
+                    paren_start = None;
                 }
+                continue;
+            }
+            if c.is_whitespace() {
+                continue;
+            }
+            match c {
                 //有效数字
-                '0'..='9' | '.' | '-' | '+' | 'E' | 'e' => {
+                '0'..='9' | '.' | '-' | '+' => {
                     value.value.push(c);
-                }
-                ';' => {
-                    //注释, 则跳过后续所有内容
-                    self._skip_until_newline(chars);
                     if !value.command.is_empty() {
-                        values.push(value);
+                        value.span.len = pos(index + c.len_utf8()) - value.span.offset;
                     }
-                    return (values, false);
+                }
+                ';' => {
+                    //注释, 后续所有内容都是注释
+                    line.comments.push(GCodeComment::new(
+                        &text[index + 1..],
+                        false,
+                        line_number,
+                        pos(index),
+                        pos(text.len()) - pos(index),
+                    ));
+                    break;
+                }
+                '(' => {
+                    paren_start = Some(index);
+                }
+                '/' if is_line_start => {
+                    line.block_delete = true;
+                }
+                '%' if is_line_start => {
+                    line.program_marker = true;
                 }
                 _ => {
-                    if self._is_newline(c) {
-                        if !value.command.is_empty() {
-                            values.push(value);
-                        }
-                        return (values, false);
+                    //字母地址/校验和/未知的指令
+                    if !value.command.is_empty() {
+                        // 处理上一个指令
+                        line.values.push(value);
                     }
+                    value = GCodeValue::new();
+                    value.command.push(c.to_ascii_uppercase());
+                    value.span = GCodeSpan {
+                        line: line_number,
+                        offset: pos(index),
+                        len: pos(index + c.len_utf8()) - pos(index),
+                    };
                 }
             }
+            is_line_start = false;
+        }
+        if let Some(start) = paren_start {
+            //未闭合的`(`注释, 直到行尾
+            line.comments.push(GCodeComment::new(
+                &text[start + 1..],
+                true,
+                line_number,
+                pos(start),
+                pos(text.len()) - pos(start),
+            ));
         }
         if !value.command.is_empty() {
-            values.push(value);
+            line.values.push(value);
         }
-        (values, true)
     }
+}
 
-    /// 跳过所有内容, 直到换行
-    fn _skip_until_newline(&self, chars: &mut Chars) {
-        while let Some(c) = chars.next() {
-            if self._is_newline(c) {
-                break;
+/// 一行数据转换成的文本, 无效的UTF-8字节会替换成`U+FFFD`
+/// - 替换字符占3个字节, 和源数据的字节数不同, 需要映射回源数据的字节偏移量
+struct GCodeLossyText {
+    /// 转换之后的文本
+    text: String,
+    /// 每一段的开始位置, (文本中的位置, 源数据中的位置), 最后一个为结尾
+    boundaries: Vec<(usize, usize)>,
+}
+
+impl GCodeLossyText {
+    fn new(bytes: &[u8]) -> Self {
+        let mut text = String::with_capacity(bytes.len());
+        let mut boundaries = vec![];
+        let mut raw = 0;
+        for chunk in bytes.utf8_chunks() {
+            boundaries.push((text.len(), raw));
+            text.push_str(chunk.valid());
+            raw += chunk.valid().len();
+            if !chunk.invalid().is_empty() {
+                boundaries.push((text.len(), raw));
+                text.push(char::REPLACEMENT_CHARACTER);
+                raw += chunk.invalid().len();
             }
         }
+        boundaries.push((text.len(), raw));
+        Self { text, boundaries }
+    }
+
+    /// 文本中的位置对应源数据中的位置, 相对于行首
+    fn raw_index(&self, index: usize) -> usize {
+        let i = self
+            .boundaries
+            .partition_point(|(text_index, _)| *text_index <= index)
+            .max(1)
+            - 1;
+        let (text_index, raw_index) = self.boundaries[i];
+        raw_index + (index - text_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{GCodeFlow, GCodeValueHandler};
+    use crate::parser::{
+        GCodeComment, GCodeLine, GCodeParser, GCodeSpan, GCodeStreamParser, GCodeValue,
+    };
+    use std::io::{BufReader, Cursor};
+
+    /// 收集所有行
+    #[derive(Default)]
+    struct LinesHandler {
+        lines: Vec<String>,
     }
 
-    /// 是否是换行符
-    fn _is_newline(&self, c: char) -> bool {
-        c == '\n' || c == '\r'
+    impl GCodeValueHandler for LinesHandler {
+        fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+            let line: Vec<String> = gcode_value_line.iter().map(|v| v.to_string()).collect();
+            self.lines.push(line.join(" "));
+            GCodeFlow::Continue
+        }
+    }
+
+    #[test]
+    fn test_gcode_stream_parser() {
+        let gcode = "G90\r\nG21 ;mm\nG0 X1 Y2\rG1 X10.5 Y-2\n\n".to_string();
+
+        let mut stream_handler = LinesHandler::default();
+        GCodeStreamParser::new(Cursor::new(gcode.as_bytes()))
+            .parse(&mut stream_handler)
+            .unwrap();
+        assert_eq!(
+            stream_handler.lines,
+            vec!["G90", "G21", "G0 X1 Y2", "G1 X10.5 Y-2"]
+        );
+
+        let mut handler = LinesHandler::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_eq!(handler.lines, stream_handler.lines);
+    }
+
+    #[test]
+    fn test_gcode_stream_parser_cr() {
+        //只使用`\r`换行, 缓冲区很小时也逐行读取, `\r\n`跨越缓冲区也只算一次换行
+        let gcode = "G90\rG21\r\nG0 X1 Y2\rG1 X10.5 Y-2\r".to_string();
+        let mut handler = GCodeLinesHandler::default();
+        let reader = BufReader::with_capacity(4, Cursor::new(gcode.as_bytes()));
+        GCodeStreamParser::new(reader).parse(&mut handler).unwrap();
+
+        let lines = &handler.lines;
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1].span, span(2, 4, 3));
+        assert_eq!(lines[2].span, span(3, 9, 8));
+        assert_eq!(lines[3].span, span(4, 18, 12));
+        assert_eq!(lines[3].text, "G1 X10.5 Y-2");
+    }
+
+    /// 收集所有[GCodeLine]和注释
+    #[derive(Default)]
+    struct GCodeLinesHandler {
+        lines: Vec<GCodeLine>,
+        comments: Vec<String>,
+    }
+
+    impl GCodeValueHandler for GCodeLinesHandler {
+        fn handle_comment(&mut self, comment: &GCodeComment) {
+            self.comments.push(comment.text.clone());
+        }
+
+        fn handle_gcode_line(&mut self, gcode_line: GCodeLine) -> GCodeFlow {
+            self.lines.push(gcode_line);
+            GCodeFlow::Continue
+        }
+
+        fn handle_gcode_value(&mut self, _gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+            GCodeFlow::Continue
+        }
+    }
+
+    fn span(line: usize, offset: usize, len: usize) -> GCodeSpan {
+        GCodeSpan { line, offset, len }
+    }
+
+    #[test]
+    fn test_gcode_line_span() {
+        let gcode = "G90\r\n;comment\nG1 X10.5 Y-2\rG0 X1\n".to_string();
+        let mut handler = GCodeLinesHandler::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+
+        let lines = &handler.lines;
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].span, span(1, 0, 3));
+        assert_eq!(lines[1].span, span(3, 14, 12));
+        assert_eq!(lines[1].text, "G1 X10.5 Y-2");
+        assert_eq!(lines[2].span, span(4, 27, 5));
+
+        let y = &lines[1].values[2];
+        assert_eq!(y.span, span(3, 23, 3));
+        assert_eq!(&gcode[y.span.offset..y.span.offset + y.span.len], "Y-2");
+    }
+
+    #[test]
+    fn test_gcode_line_span_invalid_utf8() {
+        //无效的UTF-8字节替换成`U+FFFD`之后, 偏移量还是源数据中的字节偏移量
+        let gcode = b"G0\nG1 (\xFF) X1\nG0 X2\n";
+        let mut handler = GCodeLinesHandler::default();
+        GCodeStreamParser::new(Cursor::new(&gcode[..]))
+            .parse(&mut handler)
+            .unwrap();
+
+        let lines = &handler.lines;
+        assert_eq!(lines[1].text, "G1 (\u{FFFD}) X1");
+        assert_eq!(lines[1].span, span(2, 3, 9));
+        assert_eq!(lines[1].comments[0].span, span(2, 6, 3));
+        let x = &lines[1].values[1];
+        assert_eq!(x.span, span(2, 10, 2));
+        assert_eq!(&gcode[x.span.offset..x.span.offset + x.span.len], b"X1");
+        assert_eq!(lines[2].values[1].span, span(3, 16, 2));
+    }
+
+    #[test]
+    fn test_gcode_word_alphabet() {
+        let gcode = "N10 G1 X10 e2.5 F1200 T1 A90 B-1.5 K0.2*57\nG1 X1E2 #1=5".to_string();
+        let mut handler = LinesHandler::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_eq!(
+            handler.lines,
+            vec![
+                "N10 G1 X10 E2.5 F1200 T1 A90 B-1.5 K0.2 *57",
+                "G1 X1 E2 #1 =5"
+            ]
+        );
+
+        let mut handler = GCodeLinesHandler::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let values = &handler.lines[1].values;
+        assert!(values[2].is_e());
+        assert!(!values[2].is_unknown());
+        assert!(values[3].is_unknown());
+        assert!(handler.lines[0].values[9].is_checksum());
+    }
+
+    #[test]
+    fn test_gcode_comment() {
+        let gcode =
+            "%\n(TOOL: 3mm endmill)\nG0 X1 (rapid; fast) Y2 ;end\n/G1 X5\n(unclosed\n%".to_string();
+        let mut handler = GCodeLinesHandler::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_eq!(
+            handler.comments,
+            vec!["TOOL: 3mm endmill", "rapid; fast", "end", "unclosed"]
+        );
+        let lines = &handler.lines;
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].program_marker);
+        assert_eq!(lines[1].values.len(), 3);
+        assert_eq!(lines[1].comments[0].span, span(3, 28, 13));
+        assert!(lines[2].block_delete);
+        assert!(lines[3].program_marker);
+
+        let mut handler = GCodeLinesHandler::default();
+        let mut parser = GCodeParser::new(&gcode);
+        parser.block_delete = true;
+        parser.parse(&mut handler);
+        assert_eq!(handler.lines.len(), 3);
     }
 }
@@ -0,0 +1,287 @@
+use crate::handler::{GCodeFlow, GCodeValueHandler};
+use crate::parser::{GCodeComment, GCodeLine, GCodeValue};
+
+///
+/// @author <a href="mailto: angcyo@126.com">angcyo</a>
+/// @date 2026/10/18
+///
+/// 将每一行数据同时交给多个处理器, 只需要解析一次
+/// - 每个处理器单独中断, 所有的处理器都中断之后才会中断解析
+/// - 借用处理器, 解析之后直接读取每个处理器的结果
+pub struct GCodeValueHandlerMulti<'a> {
+    /// 处理器
+    handlers: Vec<&'a mut dyn GCodeValueHandler>,
+    /// 每个处理器是否已经中断
+    aborted: Vec<bool>,
+}
+
+impl<'a> GCodeValueHandlerMulti<'a> {
+    pub fn new(handlers: Vec<&'a mut dyn GCodeValueHandler>) -> Self {
+        let aborted = vec![false; handlers.len()];
+        Self { handlers, aborted }
+    }
+
+    /// 添加一个处理器
+    pub fn push(&mut self, handler: &'a mut dyn GCodeValueHandler) {
+        self.handlers.push(handler);
+        self.aborted.push(false);
+    }
+
+    /// 交给每一个没有中断的处理器
+    fn dispatch(
+        &mut self,
+        mut handle: impl FnMut(&mut dyn GCodeValueHandler) -> GCodeFlow,
+    ) -> GCodeFlow {
+        for (handler, aborted) in self.handlers.iter_mut().zip(self.aborted.iter_mut()) {
+            if !*aborted {
+                *aborted = handle(&mut **handler) == GCodeFlow::Abort;
+            }
+        }
+        if self.aborted.iter().all(|aborted| *aborted) {
+            GCodeFlow::Abort
+        } else {
+            GCodeFlow::Continue
+        }
+    }
+}
+
+impl GCodeValueHandler for GCodeValueHandlerMulti<'_> {
+    fn start(&mut self) {
+        self.aborted.fill(false);
+        for handler in self.handlers.iter_mut() {
+            handler.start();
+        }
+    }
+
+    fn handle_comment(&mut self, comment: &GCodeComment) {
+        self.dispatch(|handler| {
+            handler.handle_comment(comment);
+            GCodeFlow::Continue
+        });
+    }
+
+    fn handle_gcode_line(&mut self, gcode_line: GCodeLine) -> GCodeFlow {
+        self.dispatch(|handler| handler.handle_gcode_line(gcode_line.clone()))
+    }
+
+    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+        self.dispatch(|handler| handler.handle_gcode_value(gcode_value_line.clone()))
+    }
+
+    fn end(&mut self) {
+        for handler in self.handlers.iter_mut() {
+            handler.end();
+        }
+    }
+}
+
+/// 改写每一行数据之后, 再交给[handler]
+/// - [map] 返回[None]时丢弃这一行
+/// - 直接调用[GCodeValueHandler::handle_gcode_value]时, 改写的[GCodeLine]只有[GCodeLine::values]
+pub struct GCodeValueHandlerMap<H, F> {
+    /// 下一个处理器
+    pub handler: H,
+    map: F,
+}
+
+impl<H, F> GCodeValueHandlerMap<H, F>
+where
+    H: GCodeValueHandler,
+    F: FnMut(GCodeLine) -> Option<GCodeLine>,
+{
+    pub fn new(handler: H, map: F) -> Self {
+        Self { handler, map }
+    }
+}
+
+impl<H, F> GCodeValueHandler for GCodeValueHandlerMap<H, F>
+where
+    H: GCodeValueHandler,
+    F: FnMut(GCodeLine) -> Option<GCodeLine>,
+{
+    fn start(&mut self) {
+        self.handler.start();
+    }
+
+    fn handle_comment(&mut self, comment: &GCodeComment) {
+        self.handler.handle_comment(comment);
+    }
+
+    fn handle_gcode_line(&mut self, gcode_line: GCodeLine) -> GCodeFlow {
+        match (self.map)(gcode_line) {
+            Some(gcode_line) => self.handler.handle_gcode_line(gcode_line),
+            None => GCodeFlow::Continue,
+        }
+    }
+
+    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+        self.handle_gcode_line(GCodeLine {
+            values: gcode_value_line,
+            ..Default::default()
+        })
+    }
+
+    fn end(&mut self) {
+        self.handler.end();
+    }
+}
+
+/// 过滤每一行数据, [filter]返回true的行才会交给[handler]
+/// - 注释不会过滤
+pub struct GCodeValueHandlerFilter<H, F> {
+    /// 下一个处理器
+    pub handler: H,
+    filter: F,
+}
+
+impl<H, F> GCodeValueHandlerFilter<H, F>
+where
+    H: GCodeValueHandler,
+    F: FnMut(&GCodeLine) -> bool,
+{
+    pub fn new(handler: H, filter: F) -> Self {
+        Self { handler, filter }
+    }
+}
+
+impl<H, F> GCodeValueHandler for GCodeValueHandlerFilter<H, F>
+where
+    H: GCodeValueHandler,
+    F: FnMut(&GCodeLine) -> bool,
+{
+    fn start(&mut self) {
+        self.handler.start();
+    }
+
+    fn handle_comment(&mut self, comment: &GCodeComment) {
+        self.handler.handle_comment(comment);
+    }
+
+    fn handle_gcode_line(&mut self, gcode_line: GCodeLine) -> GCodeFlow {
+        if (self.filter)(&gcode_line) {
+            self.handler.handle_gcode_line(gcode_line)
+        } else {
+            GCodeFlow::Continue
+        }
+    }
+
+    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+        self.handle_gcode_line(GCodeLine {
+            values: gcode_value_line,
+            ..Default::default()
+        })
+    }
+
+    fn end(&mut self) {
+        self.handler.end();
+    }
+}
+
+/// 组合处理器
+/// - `handler.map_line(..).filter_line(..)`, 先过滤再改写, 最后交给`handler`
+pub trait GCodeValueHandlerExt: GCodeValueHandler + Sized {
+    /// 改写每一行数据之后再交给自己, 参考[GCodeValueHandlerMap]
+    fn map_line<F>(self, map: F) -> GCodeValueHandlerMap<Self, F>
+    where
+        F: FnMut(GCodeLine) -> Option<GCodeLine>,
+    {
+        GCodeValueHandlerMap::new(self, map)
+    }
+
+    /// 过滤每一行数据之后再交给自己, 参考[GCodeValueHandlerFilter]
+    fn filter_line<F>(self, filter: F) -> GCodeValueHandlerFilter<Self, F>
+    where
+        F: FnMut(&GCodeLine) -> bool,
+    {
+        GCodeValueHandlerFilter::new(self, filter)
+    }
+}
+
+impl<H: GCodeValueHandler> GCodeValueHandlerExt for H {}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{GCodeFlow, GCodeValueHandler, GCodeValueHandlerPath};
+    use crate::metadata::GCodeValueHandlerMetadata;
+    use crate::parser::{GCodeParser, GCodeValue};
+    use crate::pipeline::{GCodeValueHandlerExt, GCodeValueHandlerMulti};
+    use crate::stats::GCodeValueHandlerStatistics;
+    use crate::writer::format_number;
+
+    /// 处理指定的行数之后中断
+    #[derive(Default)]
+    struct TakeHandler {
+        take: usize,
+        count: usize,
+        ended: bool,
+    }
+
+    impl GCodeValueHandler for TakeHandler {
+        fn handle_gcode_value(&mut self, _gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+            self.count += 1;
+            if self.count >= self.take {
+                GCodeFlow::Abort
+            } else {
+                GCodeFlow::Continue
+            }
+        }
+
+        fn end(&mut self) {
+            self.ended = true;
+        }
+    }
+
+    #[test]
+    fn test_gcode_multi_handler() {
+        let gcode = ";FLAVOR:Marlin\nG0 X10 Y10\nG1 X20 F1000\nG1 Y20\nG0 Z1\nG1 X0".to_string();
+        let mut statistics = GCodeValueHandlerStatistics::default();
+        let mut path = GCodeValueHandlerPath::default();
+        let mut metadata = GCodeValueHandlerMetadata::default();
+        let mut take = TakeHandler {
+            take: 2,
+            ..Default::default()
+        };
+        GCodeParser::new(&gcode).parse(&mut GCodeValueHandlerMulti::new(vec![
+            &mut statistics,
+            &mut path,
+            &mut metadata,
+            &mut take,
+        ]));
+        assert_eq!(statistics.statistics.line_count, 5);
+        assert_eq!(statistics.statistics.cut_length, 40.0);
+        assert_eq!(path.layers.len(), 2);
+        assert_eq!(metadata.metadata.settings["FLAVOR"], "Marlin");
+        assert_eq!(take.count, 2);
+        assert!(take.ended);
+
+        //单独使用时中断解析
+        let mut take = TakeHandler {
+            take: 2,
+            ..Default::default()
+        };
+        GCodeParser::new(&gcode).parse(&mut take);
+        assert_eq!(take.count, 2);
+        assert!(take.ended);
+    }
+
+    #[test]
+    fn test_gcode_map_filter_handler() {
+        let gcode = "G0 X10 Y10\nM3 S1000\nG1 X20\nM5\nG1 Y20".to_string();
+        let mut statistics = GCodeValueHandlerStatistics::default();
+        let mut handler = (&mut statistics)
+            .map_line(|mut line| {
+                //X偏移10
+                for value in line.values.iter_mut().filter(|value| value.is_x()) {
+                    value.value = format_number(value.value_f64() + 10.0, 6);
+                }
+                Some(line)
+            })
+            .filter_line(|line| line.values.iter().all(|value| value.command != "M"));
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let statistics = &statistics.statistics;
+        assert_eq!(statistics.line_count, 3);
+        assert!(!statistics.commands.contains_key("M3"));
+        let bounds = statistics.bounds.unwrap();
+        assert_eq!((bounds.min_x, bounds.max_x), (20.0, 30.0));
+    }
+}
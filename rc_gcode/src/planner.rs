@@ -0,0 +1,520 @@
+use crate::command::{GCodeCommand, GCodeFeedMode};
+use crate::handler::{GCodeFlow, GCodeValueHandler};
+use crate::metadata::is_layer_comment;
+use crate::modal::{GCodeModalState, GCodeMotionMode, GCodePosition};
+use crate::parser::{GCodeComment, GCodeValue};
+use std::collections::{BTreeMap, VecDeque};
+
+///
+/// @author <a href="mailto: angcyo@126.com">angcyo</a>
+/// @date 2026/10/18
+///
+/// 运动规划的参数, 数组中的顺序为X/Y/Z轴
+#[derive(Clone, Debug)]
+pub struct GCodePlannerConfig {
+    /// 每个轴的最大速度, 单位mm/min, `G0`使用这个速度
+    pub max_feed_rate: [f64; 3],
+    /// 每个轴的最大加速度, 单位mm/s²
+    pub acceleration: [f64; 3],
+    /// GRBL的拐角偏差, 单位mm
+    pub junction_deviation: f64,
+    /// Marlin每个轴的最大瞬时速度变化, 单位mm/s
+    /// - 有值时使用Jerk计算拐角速度, 否则使用[junction_deviation]
+    pub jerk: Option<[f64; 3]>,
+    /// 圆弧/样条分段的误差, 单位mm
+    pub arc_tolerance: f64,
+    /// 没有设置`F`时使用的进给速度, 单位mm/min
+    pub default_feed_rate: f64,
+    /// `G4 P`的单位是否是毫秒, 比如Marlin, 否则为秒
+    pub dwell_p_milliseconds: bool,
+    /// 前瞻的运动段数, 和GRBL/Marlin的`BLOCK_BUFFER_SIZE`相同
+    /// - 只在这些运动中规划速度, 最后一段按照停止规划
+    pub block_buffer_size: usize,
+}
+
+impl Default for GCodePlannerConfig {
+    fn default() -> Self {
+        GCodePlannerConfig {
+            max_feed_rate: [6000.0, 6000.0, 1000.0],
+            acceleration: [1000.0, 1000.0, 100.0],
+            junction_deviation: 0.01,
+            jerk: None,
+            arc_tolerance: 0.002,
+            default_feed_rate: 1000.0,
+            dwell_p_milliseconds: false,
+            block_buffer_size: 16,
+        }
+    }
+}
+
+/// 预估的时间, 单位秒
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GCodeTimeEstimate {
+    /// 总时间
+    pub total_time: f64,
+    /// 暂停`G4`的时间
+    pub dwell_time: f64,
+    /// 每一层的时间, 只有Z的移动或者层标记注释会开始新的一层
+    pub layers: Vec<GCodeLayerTime>,
+    /// 每一把刀具的时间
+    pub tools: BTreeMap<u32, f64>,
+}
+
+/// 一层的时间
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GCodeLayerTime {
+    /// 当前层的Z坐标, 机床坐标, 单位mm
+    pub z: f64,
+    /// 时间, 单位秒
+    pub time: f64,
+}
+
+/// 规划的一段运动
+#[derive(Clone, Debug)]
+struct GCodePlannerBlock {
+    /// 长度, 单位mm
+    length: f64,
+    /// 单位方向向量
+    unit: [f64; 3],
+    /// 名义速度, 单位mm/s
+    nominal_speed: f64,
+    /// 加速度, 单位mm/s²
+    acceleration: f64,
+    /// 最大的进入速度, 单位mm/s
+    max_entry_speed: f64,
+    /// 进入速度, 规划之后的值
+    entry_speed: f64,
+    /// 暂停的时间, 单位秒, 有值时这一段不是运动
+    dwell: f64,
+    /// 所在的层
+    layer: usize,
+    /// 刀具编号
+    tool: u32,
+}
+
+/// 使用GRBL/Marlin的梯形速度规划预估加工时间
+/// - 拐角速度使用拐角偏差或者Jerk计算
+/// - 圆弧/样条按照[GCodePlannerConfig::arc_tolerance]分段
+/// - `G4`会停止运动
+/// - 开启[layer_comments]时, 使用切片软件的层标记分层, 层中z-hop的Z会被忽略
+/// - 只保存[GCodePlannerConfig::block_buffer_size]段运动, 超出时最早的一段不会再改变, 计算时间之后丢弃
+/// - 结果在[end]之后保存在[estimate]中
+pub struct GCodeValueHandlerPlanner {
+    /// 规划的参数
+    pub config: GCodePlannerConfig,
+    /// 模态状态, 在行与行之间保持
+    pub modal: GCodeModalState,
+    /// 预估的时间
+    pub estimate: GCodeTimeEstimate,
+    /// 是否使用`;LAYER:`/`;LAYER_CHANGE`注释分层, 而不是只有Z的移动
+    pub layer_comments: bool,
+    //--
+    /// 层标记之后还没有XY的运动, 这时Z的移动是这一层的Z
+    is_layer_open: bool,
+    /// 前瞻窗口中的运动, 第一段的进入速度已经确定
+    blocks: VecDeque<GCodePlannerBlock>,
+}
+
+impl Default for GCodeValueHandlerPlanner {
+    fn default() -> Self {
+        GCodeValueHandlerPlanner::new(GCodePlannerConfig::default())
+    }
+}
+
+impl GCodeValueHandlerPlanner {
+    pub fn new(config: GCodePlannerConfig) -> Self {
+        GCodeValueHandlerPlanner {
+            config,
+            modal: GCodeModalState::default(),
+            estimate: GCodeTimeEstimate::default(),
+            layer_comments: false,
+            is_layer_open: false,
+            blocks: VecDeque::new(),
+        }
+    }
+
+    /// 当前所在的层
+    fn layer(&self) -> usize {
+        self.estimate.layers.len().saturating_sub(1)
+    }
+
+    /// 当前运动的名义速度, 单位mm/min
+    /// - [length] 运动的总长度, 反比时间模式使用
+    fn feed_rate(&self, mode: GCodeMotionMode, length: f64) -> f64 {
+        if mode == GCodeMotionMode::Rapid {
+            return f64::MAX;
+        }
+        let feed_rate = match self.modal.feed_mode {
+            GCodeFeedMode::InverseTime => self.modal.feed_rate * length,
+            GCodeFeedMode::UnitsPerMinute => self.modal.feed_rate_mm(),
+            GCodeFeedMode::UnitsPerRevolution => {
+                self.modal.feed_rate * self.modal.unit_scale() * self.modal.spindle_speed
+            }
+        };
+        if feed_rate > 0.0 {
+            feed_rate
+        } else {
+            self.config.default_feed_rate
+        }
+    }
+
+    /// 添加一段直线运动
+    /// - [feed_rate] 名义速度, 单位mm/min
+    fn push_line(&mut self, from: &GCodePosition, to: &GCodePosition, feed_rate: f64) {
+        let delta = [to.x - from.x, to.y - from.y, to.z - from.z];
+        let length = (delta[0] * delta[0] + delta[1] * delta[1] + delta[2] * delta[2]).sqrt();
+        if length < 1e-9 {
+            return;
+        }
+        let unit = delta.map(|d| d / length);
+
+        //每个轴的限制
+        let mut nominal_speed = feed_rate / 60.0;
+        let mut acceleration = f64::MAX;
+        for (i, u) in unit.iter().enumerate() {
+            let u = u.abs();
+            if u > 1e-9 {
+                nominal_speed = nominal_speed.min(self.config.max_feed_rate[i] / 60.0 / u);
+                acceleration = acceleration.min(self.config.acceleration[i] / u);
+            }
+        }
+
+        let max_entry_speed = match self.blocks.back() {
+            Some(prev) if prev.dwell <= 0.0 => {
+                let junction_speed = self.junction_speed(prev, &unit, acceleration);
+                junction_speed.min(prev.nominal_speed).min(nominal_speed)
+            }
+            _ => 0.0,
+        };
+        self.push_block(GCodePlannerBlock {
+            length,
+            unit,
+            nominal_speed,
+            acceleration,
+            max_entry_speed,
+            entry_speed: 0.0,
+            dwell: 0.0,
+            layer: self.layer(),
+            tool: self.modal.tool,
+        });
+    }
+
+    /// 计算两段运动之间的最大拐角速度, 单位mm/s
+    fn junction_speed(&self, prev: &GCodePlannerBlock, unit: &[f64; 3], acceleration: f64) -> f64 {
+        if let Some(jerk) = self.config.jerk {
+            //每个轴的速度变化不能超过jerk
+            return (0..3)
+                .map(|i| {
+                    let delta = (unit[i] - prev.unit[i]).abs();
+                    if delta > 1e-9 {
+                        jerk[i] / delta
+                    } else {
+                        f64::MAX
+                    }
+                })
+                .fold(f64::MAX, f64::min);
+        }
+        let cos_theta = -(0..3).map(|i| prev.unit[i] * unit[i]).sum::<f64>();
+        if cos_theta > 0.999999 {
+            //掉头
+            0.0
+        } else if cos_theta < -0.999999 {
+            //直线
+            f64::MAX
+        } else {
+            let sin_theta_d2 = (0.5 * (1.0 - cos_theta)).sqrt();
+            (acceleration * self.config.junction_deviation * sin_theta_d2 / (1.0 - sin_theta_d2))
+                .sqrt()
+        }
+    }
+
+    /// 处理一行指令, 固定循环展开之后的
+    fn handle_commands(&mut self, commands: &[GCodeCommand]) {
+        for command in commands {
+            if let GCodeCommand::Dwell(params) = command {
+                let dwell = match (params.s, params.p) {
+                    (Some(s), _) => s,
+                    (None, Some(p)) if self.config.dwell_p_milliseconds => p / 1000.0,
+                    (None, Some(p)) => p,
+                    _ => 0.0,
+                };
+                self.push_dwell(dwell);
+            }
+        }
+        let Some(motion) = self.modal.apply(commands) else {
+            return;
+        };
+        if !motion.have_xy() && motion.params.z.is_some() && motion.cycle.is_none() {
+            if !self.layer_comments {
+                //只有Z的移动, 新的一层, 固定循环中的除外
+                self.estimate.layers.push(GCodeLayerTime {
+                    z: motion.to.z,
+                    time: 0.0,
+                });
+            } else if self.is_layer_open {
+                //层标记之后的Z, 之后的是z-hop
+                if let Some(layer) = self.estimate.layers.last_mut() {
+                    layer.z = motion.to.z;
+                }
+            }
+        }
+        if motion.have_xy() {
+            self.is_layer_open = false;
+        }
+
+        let points = motion.flatten(self.config.arc_tolerance);
+        let feed_rate = self.feed_rate(motion.mode, motion.length());
+        let mut from = motion.from;
+        for to in points {
+            self.push_line(&from, &to, feed_rate);
+            from = to;
+        }
+    }
+
+    /// 添加一段暂停
+    fn push_dwell(&mut self, dwell: f64) {
+        self.push_block(GCodePlannerBlock {
+            length: 0.0,
+            unit: [0.0; 3],
+            nominal_speed: 0.0,
+            acceleration: 0.0,
+            max_entry_speed: 0.0,
+            entry_speed: 0.0,
+            dwell,
+            layer: self.layer(),
+            tool: self.modal.tool,
+        });
+    }
+
+    /// 添加一段运动到前瞻窗口, 窗口满了之后计算最早的一段的时间
+    fn push_block(&mut self, block: GCodePlannerBlock) {
+        self.blocks.push_back(block);
+        if self.blocks.len() > self.config.block_buffer_size.max(2) {
+            self.plan();
+            self.pop_block();
+        }
+    }
+
+    /// 规划前瞻窗口中的运动, 第一段的进入速度不会改变
+    fn plan(&mut self) {
+        let blocks = &mut self.blocks;
+        //反向, 保证能减速到下一段的进入速度, 最后一段停止
+        let mut exit_speed = 0.0;
+        for block in blocks.iter_mut().skip(1).rev() {
+            let speed = (exit_speed * exit_speed + 2.0 * block.acceleration * block.length).sqrt();
+            block.entry_speed = block.max_entry_speed.min(speed);
+            exit_speed = block.entry_speed;
+        }
+        //正向, 保证能从上一段的进入速度加速到
+        for i in 1..blocks.len() {
+            let prev = &blocks[i - 1];
+            let speed = (prev.entry_speed * prev.entry_speed
+                + 2.0 * prev.acceleration * prev.length)
+                .sqrt();
+            blocks[i].entry_speed = blocks[i].entry_speed.min(speed);
+        }
+    }
+
+    /// 计算最早的一段的时间并丢弃, 下一段的进入速度就是这一段的退出速度, 之后不会再改变
+    fn pop_block(&mut self) {
+        let Some(block) = self.blocks.pop_front() else {
+            return;
+        };
+        let time = if block.dwell > 0.0 {
+            self.estimate.dwell_time += block.dwell;
+            block.dwell
+        } else {
+            let exit_speed = self.blocks.front().map(|b| b.entry_speed).unwrap_or(0.0);
+            trapezoid_time(
+                block.length,
+                block.entry_speed,
+                exit_speed,
+                block.nominal_speed,
+                block.acceleration,
+            )
+        };
+        self.estimate.total_time += time;
+        self.estimate.layers[block.layer].time += time;
+        *self.estimate.tools.entry(block.tool).or_insert(0.0) += time;
+    }
+}
+
+/// 梯形速度曲线运动的时间, 单位秒
+/// - [length] 长度, 单位mm
+/// - [entry_speed] / [exit_speed] / [nominal_speed] 速度, 单位mm/s
+/// - [acceleration] 加速度, 单位mm/s²
+pub fn trapezoid_time(
+    length: f64,
+    entry_speed: f64,
+    exit_speed: f64,
+    nominal_speed: f64,
+    acceleration: f64,
+) -> f64 {
+    let accelerate_distance =
+        (nominal_speed * nominal_speed - entry_speed * entry_speed) / (2.0 * acceleration);
+    let decelerate_distance =
+        (nominal_speed * nominal_speed - exit_speed * exit_speed) / (2.0 * acceleration);
+    if accelerate_distance + decelerate_distance <= length {
+        (nominal_speed - entry_speed) / acceleration
+            + (nominal_speed - exit_speed) / acceleration
+            + (length - accelerate_distance - decelerate_distance) / nominal_speed
+    } else {
+        //达不到名义速度, 三角形
+        let peak_speed =
+            ((2.0 * acceleration * length + entry_speed * entry_speed + exit_speed * exit_speed)
+                / 2.0)
+                .sqrt();
+        (peak_speed - entry_speed) / acceleration + (peak_speed - exit_speed) / acceleration
+    }
+}
+
+impl GCodeValueHandler for GCodeValueHandlerPlanner {
+    fn start(&mut self) {
+        self.estimate = GCodeTimeEstimate::default();
+        self.is_layer_open = false;
+        self.blocks.clear();
+    }
+
+    fn handle_comment(&mut self, comment: &GCodeComment) {
+        if self.layer_comments && is_layer_comment(comment) {
+            self.estimate.layers.push(GCodeLayerTime {
+                z: self.modal.position.z,
+                time: 0.0,
+            });
+            self.is_layer_open = true;
+        }
+    }
+
+    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+        if self.estimate.layers.is_empty() {
+            self.estimate.layers.push(GCodeLayerTime {
+                z: self.modal.position.z,
+                time: 0.0,
+            });
+        }
+        let commands = GCodeCommand::from_values(&gcode_value_line);
+        for commands in self.modal.expand_cycle(&commands) {
+            self.handle_commands(&commands);
+        }
+        GCodeFlow::Continue
+    }
+
+    fn end(&mut self) {
+        self.plan();
+        while !self.blocks.is_empty() {
+            self.pop_block();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{GCodeFlow, GCodeValueHandler};
+    use crate::parser::{GCodeParser, GCodeValue};
+    use crate::planner::{GCodePlannerConfig, GCodeValueHandlerPlanner};
+
+    /// 记录解析过程中前瞻窗口的最大段数
+    struct WindowHandler {
+        planner: GCodeValueHandlerPlanner,
+        max_blocks: usize,
+    }
+
+    impl GCodeValueHandler for WindowHandler {
+        fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+            let flow = self.planner.handle_gcode_value(gcode_value_line);
+            self.max_blocks = self.max_blocks.max(self.planner.blocks.len());
+            flow
+        }
+
+        fn end(&mut self) {
+            self.planner.end();
+        }
+    }
+
+    fn estimate(gcode: &str, config: GCodePlannerConfig) -> GCodeValueHandlerPlanner {
+        let gcode = gcode.to_string();
+        let mut handler = GCodeValueHandlerPlanner::new(config);
+        GCodeParser::new(&gcode).parse(&mut handler);
+        handler
+    }
+
+    #[track_caller]
+    fn assert_time(time: f64, expected: f64) {
+        assert!((time - expected).abs() < 1e-6, "{} != {}", time, expected);
+    }
+
+    #[test]
+    fn test_gcode_planner() {
+        //加速0.1s 5mm, 匀速0.9s 90mm, 减速0.1s 5mm
+        let config = GCodePlannerConfig::default();
+        let handler = estimate("G1 X100 F6000", config.clone());
+        assert_time(handler.estimate.total_time, 1.1);
+
+        //共线的两段不会减速
+        let handler = estimate("G1 X50 F6000\nX100", config.clone());
+        assert_time(handler.estimate.total_time, 1.1);
+
+        //拐角需要减速
+        let handler = estimate("G1 X50 F6000\nY50", config.clone());
+        assert!(handler.estimate.total_time > 1.1);
+        assert!(handler.estimate.total_time < 1.2);
+
+        //暂停/刀具/层
+        let handler = estimate(
+            "T1\nG1 X100 F6000\nG4 P1.5\nG0 Z1\nT2\nG1 X0",
+            config.clone(),
+        );
+        let result = &handler.estimate;
+        assert_time(result.dwell_time, 1.5);
+        assert_eq!(result.layers.len(), 2);
+        assert_time(result.layers[0].time, 2.6);
+        assert_eq!(result.layers[1].z, 1.0);
+        //从Z的拐角进入, 不是从静止开始
+        assert!(result.tools[&2] > 1.0 && result.tools[&2] < 1.1);
+        assert_time(result.total_time, result.tools.values().sum());
+        assert_time(
+            result.total_time,
+            result.layers.iter().map(|l| l.time).sum(),
+        );
+
+        //前瞻窗口太小时, 很多共线的短线段也需要减速
+        let gcode = (1..=100)
+            .map(|i| format!("G1 X{} F6000", i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut handler = WindowHandler {
+            planner: GCodeValueHandlerPlanner::new(config.clone()),
+            max_blocks: 0,
+        };
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_time(handler.planner.estimate.total_time, 1.1);
+        assert_eq!(handler.max_blocks, config.block_buffer_size);
+        let handler = estimate(
+            &gcode,
+            GCodePlannerConfig {
+                block_buffer_size: 4,
+                ..config.clone()
+            },
+        );
+        assert!(handler.estimate.total_time > 1.15);
+
+        //使用层标记分层, z-hop不会分层
+        let gcode = ";LAYER:0\nG0 Z0.2\nG1 X10 F600\nG0 Z0.6\nG0 X20\nG0 Z0.2\nG1 X30\n;LAYER:1\nG0 Z0.4\nG1 X0";
+        let mut handler = GCodeValueHandlerPlanner::new(config.clone());
+        handler.layer_comments = true;
+        GCodeParser::new(&gcode.to_string()).parse(&mut handler);
+        let result = &handler.estimate;
+        let z: Vec<f64> = result.layers.iter().map(|l| l.z).collect();
+        assert_eq!(z, vec![0.2, 0.4]);
+        assert_time(
+            result.total_time,
+            result.layers.iter().map(|l| l.time).sum(),
+        );
+        assert_eq!(estimate(gcode, config.clone()).estimate.layers.len(), 5);
+
+        //圆弧和直线的长度相同时, 时间接近
+        let handler = estimate("G92 X10\nG3 X10 I-10 F600", config.clone());
+        let circle = std::f64::consts::TAU * 10.0 / 10.0;
+        assert!((handler.estimate.total_time - circle).abs() < 0.1);
+    }
+}
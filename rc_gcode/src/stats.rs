@@ -0,0 +1,415 @@
+use crate::command::GCodeCommand;
+use crate::handler::{GCodeFlow, GCodeValueHandler};
+use crate::metadata::is_layer_comment;
+use crate::modal::{GCodeArc, GCodeModalState, GCodeMotion, GCodeSpline};
+use crate::parser::{GCodeComment, GCodeValue};
+use crate::writer::format_number;
+use lyon_path::geom::{CubicBezierSegment, QuadraticBezierSegment, point};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::f64::consts::FRAC_PI_2;
+
+///
+/// @author <a href="mailto: angcyo@126.com">angcyo</a>
+/// @date 2026/10/18
+///
+/// GCode的统计数据, 长度单位mm, 坐标为机床坐标
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GCodeStatistics {
+    /// 切割的长度
+    pub cut_length: f64,
+    /// 空走的长度
+    pub travel_length: f64,
+    /// 开始切割的次数, 也就是激光打开/穿孔的次数
+    pub laser_on_count: usize,
+    /// 切割的范围
+    pub bounds: Option<GCodeBounds>,
+    /// 每一层的统计, 只有Z的移动或者层标记注释会开始新的一层
+    pub layers: Vec<GCodeLayerStatistics>,
+    /// 切割的最小进给速度, mm/min
+    pub min_feed_rate: Option<f64>,
+    /// 切割的最大进给速度, mm/min
+    pub max_feed_rate: Option<f64>,
+    /// 切割的最小功率`S`
+    pub min_power: Option<f64>,
+    /// 切割的最大功率`S`
+    pub max_power: Option<f64>,
+    /// 挤出的总长度
+    pub extrusion: f64,
+    /// 回抽的总长度
+    pub retraction: f64,
+    /// 每个指令出现的次数, 比如`G1`/`M3`/`T1`
+    pub commands: BTreeMap<String, usize>,
+    /// 数据行数
+    pub line_count: usize,
+}
+
+/// 一层的统计数据
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GCodeLayerStatistics {
+    /// 当前层的Z坐标
+    pub z: f64,
+    /// 切割的长度
+    pub cut_length: f64,
+    /// 空走的长度
+    pub travel_length: f64,
+    /// 切割的范围
+    pub bounds: Option<GCodeBounds>,
+}
+
+/// XY平面的范围
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GCodeBounds {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl GCodeBounds {
+    pub fn width(&self) -> f64 {
+        self.max_x - self.min_x
+    }
+
+    pub fn height(&self) -> f64 {
+        self.max_y - self.min_y
+    }
+
+    /// 扩展范围, 包含指定的点
+    pub fn add_point(bounds: &mut Option<GCodeBounds>, x: f64, y: f64) {
+        match bounds {
+            Some(b) => {
+                b.min_x = b.min_x.min(x);
+                b.min_y = b.min_y.min(y);
+                b.max_x = b.max_x.max(x);
+                b.max_y = b.max_y.max(y);
+            }
+            None => {
+                *bounds = Some(GCodeBounds {
+                    min_x: x,
+                    min_y: y,
+                    max_x: x,
+                    max_y: y,
+                })
+            }
+        }
+    }
+
+    /// 扩展范围, 包含整个运动, 圆弧会包含经过的象限点, 样条使用曲线的范围
+    pub fn add_motion(bounds: &mut Option<GCodeBounds>, motion: &GCodeMotion) {
+        GCodeBounds::add_point(bounds, motion.from.x, motion.from.y);
+        GCodeBounds::add_point(bounds, motion.to.x, motion.to.y);
+        if let Some(arc) = &motion.arc {
+            //XY平面以外的圆弧, 象限点投影到XY平面
+            for i in 0..4 {
+                let angle = FRAC_PI_2 * i as f64;
+                if let Some(t) = arc_angle_t(arc, angle) {
+                    let position = motion.arc_position(arc, t);
+                    GCodeBounds::add_point(bounds, position.x, position.y);
+                }
+            }
+        }
+        let (from, to) = (
+            point(motion.from.x, motion.from.y),
+            point(motion.to.x, motion.to.y),
+        );
+        let spline_bounds = match motion.spline {
+            Some(GCodeSpline::Cubic { ctrl1, ctrl2 }) => Some(
+                CubicBezierSegment {
+                    from,
+                    ctrl1: point(ctrl1.0, ctrl1.1),
+                    ctrl2: point(ctrl2.0, ctrl2.1),
+                    to,
+                }
+                .bounding_box(),
+            ),
+            Some(GCodeSpline::Quadratic { ctrl }) => Some(
+                QuadraticBezierSegment {
+                    from,
+                    ctrl: point(ctrl.0, ctrl.1),
+                    to,
+                }
+                .bounding_box(),
+            ),
+            None => None,
+        };
+        if let Some(b) = spline_bounds {
+            GCodeBounds::add_point(bounds, b.min.x, b.min.y);
+            GCodeBounds::add_point(bounds, b.max.x, b.max.y);
+        }
+    }
+}
+
+/// 圆弧第一次经过指定角度的位置`0~1`, 不经过时返回[None]
+fn arc_angle_t(arc: &GCodeArc, angle: f64) -> Option<f64> {
+    let tau = std::f64::consts::TAU;
+    //从起点沿着圆弧方向到指定角度需要转过的角度
+    let delta = ((angle - arc.start_angle) * arc.sweep_angle.signum()).rem_euclid(tau);
+    if delta <= arc.sweep_angle.abs() {
+        Some(delta / arc.sweep_angle.abs())
+    } else {
+        None
+    }
+}
+
+/// 统计GCode数据
+/// - 切割/空走的判断和[crate::handler::GCodeValueHandlerPath]相同
+/// - 结果在[statistics]中
+pub struct GCodeValueHandlerStatistics {
+    /// 统计数据
+    pub statistics: GCodeStatistics,
+    /// 模态状态, 在行与行之间保持
+    pub modal: GCodeModalState,
+    /// 激光模式, 参考[GCodeModalState::is_cut]
+    pub laser_mode: bool,
+    /// 激光模式下`G0`是否总是关闭激光, 参考[GCodeModalState::is_cut]
+    pub rapid_laser_off: bool,
+    /// 是否使用`;LAYER:`/`;LAYER_CHANGE`注释分层, 而不是只有Z的移动
+    /// - 层中z-hop的Z会被忽略
+    pub layer_comments: bool,
+    //--
+    /// 层标记之后还没有XY的运动, 这时Z的移动是这一层的Z
+    is_layer_open: bool,
+    /// 上一次的运动是否是切割, 激光/主轴关闭和暂停也会结束切割
+    is_cutting: bool,
+}
+
+impl Default for GCodeValueHandlerStatistics {
+    fn default() -> Self {
+        GCodeValueHandlerStatistics {
+            statistics: GCodeStatistics::default(),
+            modal: GCodeModalState::default(),
+            laser_mode: false,
+            rapid_laser_off: true,
+            layer_comments: false,
+            is_layer_open: false,
+            is_cutting: false,
+        }
+    }
+}
+
+impl GCodeValueHandlerStatistics {
+    /// 当前层
+    fn layer(&mut self) -> &mut GCodeLayerStatistics {
+        if self.statistics.layers.is_empty() {
+            self.statistics.layers.push(GCodeLayerStatistics {
+                z: self.modal.position.z,
+                ..Default::default()
+            });
+        }
+        self.statistics.layers.last_mut().unwrap()
+    }
+
+    /// 激光/主轴是否打开并且功率>0
+    fn is_laser_on(&self) -> bool {
+        self.modal.is_spindle_on() && self.modal.spindle_speed > 0.0
+    }
+
+    /// 统计一段运动
+    fn handle_motion(&mut self, motion: &GCodeMotion) {
+        if motion.extrusion > 0.0 {
+            self.statistics.extrusion += motion.extrusion;
+        } else {
+            self.statistics.retraction -= motion.extrusion;
+        }
+        if !motion.have_xy() && motion.params.z.is_some() && motion.cycle.is_none() {
+            if !self.layer_comments {
+                //只有Z的移动, 新的一层, 固定循环中的除外
+                self.layer();
+                self.statistics.layers.push(GCodeLayerStatistics {
+                    z: motion.to.z,
+                    ..Default::default()
+                });
+            } else if self.is_layer_open {
+                //层标记之后的Z, 之后的是z-hop
+                self.layer().z = motion.to.z;
+            }
+        }
+        if motion.have_xy() {
+            self.is_layer_open = false;
+        }
+        let length = motion.length();
+        if length <= 0.0 {
+            //只有挤出轴的运动
+            return;
+        }
+        let is_cut = self
+            .modal
+            .is_cut(motion.mode, self.laser_mode, self.rapid_laser_off);
+        if !is_cut {
+            self.is_cutting = false;
+            self.statistics.travel_length += length;
+            self.layer().travel_length += length;
+            return;
+        }
+
+        if !self.is_cutting {
+            self.is_cutting = true;
+            self.statistics.laser_on_count += 1;
+        }
+        let feed_rate = self.modal.feed_rate_mm();
+        let power = self.modal.spindle_speed;
+        let statistics = &mut self.statistics;
+        statistics.cut_length += length;
+        statistics.min_feed_rate = Some(
+            statistics
+                .min_feed_rate
+                .map_or(feed_rate, |v| v.min(feed_rate)),
+        );
+        statistics.max_feed_rate = Some(
+            statistics
+                .max_feed_rate
+                .map_or(feed_rate, |v| v.max(feed_rate)),
+        );
+        statistics.min_power = Some(statistics.min_power.map_or(power, |v| v.min(power)));
+        statistics.max_power = Some(statistics.max_power.map_or(power, |v| v.max(power)));
+        GCodeBounds::add_motion(&mut statistics.bounds, motion);
+
+        let layer = self.layer();
+        layer.cut_length += length;
+        GCodeBounds::add_motion(&mut layer.bounds, motion);
+    }
+}
+
+impl GCodeValueHandler for GCodeValueHandlerStatistics {
+    fn start(&mut self) {
+        self.statistics = GCodeStatistics::default();
+        self.is_layer_open = false;
+        self.is_cutting = false;
+    }
+
+    fn handle_comment(&mut self, comment: &GCodeComment) {
+        if self.layer_comments && is_layer_comment(comment) {
+            let z = self.modal.position.z;
+            self.statistics.layers.push(GCodeLayerStatistics {
+                z,
+                ..Default::default()
+            });
+            self.is_layer_open = true;
+        }
+    }
+
+    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+        self.statistics.line_count += 1;
+        for value in &gcode_value_line {
+            if matches!(value.command.as_str(), "G" | "M" | "T") {
+                let command = format!("{}{}", value.command, format_number(value.value_f64(), 3));
+                *self.statistics.commands.entry(command).or_insert(0) += 1;
+            }
+        }
+
+        let commands = GCodeCommand::from_values(&gcode_value_line);
+        for commands in self.modal.expand_cycle(&commands) {
+            let was_on = self.is_laser_on();
+            let motion = self.modal.apply(&commands);
+            let is_dwell = commands
+                .iter()
+                .any(|command| matches!(command, GCodeCommand::Dwell(_)));
+            if is_dwell || (was_on && !self.is_laser_on()) {
+                //`M5`/`S0`/`G4`之后重新出光, 算作一次新的穿孔
+                self.is_cutting = false;
+            }
+            if let Some(motion) = motion {
+                self.handle_motion(&motion);
+            }
+        }
+        GCodeFlow::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::GCodeParser;
+    use crate::stats::{GCodeBounds, GCodeStatistics, GCodeValueHandlerStatistics};
+
+    #[test]
+    fn test_gcode_statistics() {
+        let gcode = "G0 X10 Y10\nM3 S500\nG1 X20 F1000\nG1 Y20\nG1 X20 Y0 S0\nG0 X0 Y0\nG0 Z1\nM3 S200\nG01 X10 E5 F600\nG1 X0 E3\nG92 E0\nM5"
+            .to_string();
+        let mut handler = GCodeValueHandlerStatistics {
+            laser_mode: true,
+            ..Default::default()
+        };
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let statistics = &handler.statistics;
+        assert_eq!(statistics.line_count, 12);
+        assert_eq!(statistics.commands["G1"], 5);
+        assert_eq!(statistics.commands["M3"], 2);
+        assert_eq!(statistics.laser_on_count, 2);
+        assert_eq!(statistics.min_power, Some(200.0));
+        assert_eq!(statistics.max_power, Some(500.0));
+        assert_eq!(statistics.min_feed_rate, Some(600.0));
+        assert_eq!(statistics.max_feed_rate, Some(1000.0));
+        assert_eq!(statistics.extrusion, 5.0);
+        assert_eq!(statistics.retraction, 2.0);
+        assert_eq!(statistics.layers.len(), 2);
+        assert_eq!(statistics.layers[1].z, 1.0);
+        assert_eq!(statistics.layers[1].cut_length, 20.0);
+
+        let json = serde_json::to_string(statistics).unwrap();
+        let value: GCodeStatistics = serde_json::from_str(&json).unwrap();
+        assert_eq!(&value, statistics);
+    }
+
+    #[test]
+    fn test_gcode_statistics_arc_bounds() {
+        let gcode = "G0 X10 Y0\nG3 X-10 Y0 I-10 J0 F100".to_string();
+        let mut handler = GCodeValueHandlerStatistics::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let statistics = &handler.statistics;
+        assert_eq!(
+            statistics.bounds,
+            Some(GCodeBounds {
+                min_x: -10.0,
+                min_y: 0.0,
+                max_x: 10.0,
+                max_y: 10.0,
+            })
+        );
+        assert!((statistics.cut_length - std::f64::consts::PI * 10.0).abs() < 1e-9);
+        assert_eq!(statistics.travel_length, 10.0);
+
+        //G18 ZX平面的整圆, 投影到XY平面
+        let gcode = "G18\nG0 X0 Y0 Z0\nG2 X0 Z0 I5 K0 F100".to_string();
+        let mut handler = GCodeValueHandlerStatistics::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let statistics = &handler.statistics;
+        let bounds = statistics.bounds.unwrap();
+        assert!((bounds.max_x - 10.0).abs() < 1e-9 && bounds.min_x.abs() < 1e-9);
+        assert!((statistics.cut_length - std::f64::consts::PI * 10.0).abs() < 1e-9);
+    }
+    #[test]
+    fn test_gcode_statistics_laser_on_count() {
+        //同一个位置关闭再打开激光, 也是一次新的穿孔
+        let gcode = "M3 S1000\nG1 X10 F600\nM5\nM3 S1000\nG1 X20\nM5\nG4 P1\nM3 S1000\nG1 X30\nS0\nS1000\nG1 X40\nG4 P1\nG1 X50"
+            .to_string();
+        let mut handler = GCodeValueHandlerStatistics {
+            laser_mode: true,
+            ..Default::default()
+        };
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_eq!(handler.statistics.laser_on_count, 5);
+    }
+    #[test]
+    fn test_gcode_statistics_layer_comments() {
+        //z-hop不会分层
+        let gcode =
+            ";LAYER:0\nG0 Z0.2\nG1 X10\nG0 Z0.6\nG0 X20\nG0 Z0.2\nG1 X30\n;LAYER:1\nG0 Z0.4\nG1 X0"
+                .to_string();
+        let mut handler = GCodeValueHandlerStatistics {
+            layer_comments: true,
+            ..Default::default()
+        };
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let layers = &handler.statistics.layers;
+        let z: Vec<f64> = layers.iter().map(|l| l.z).collect();
+        assert_eq!(z, vec![0.2, 0.4]);
+        assert_eq!(layers[0].cut_length, 20.0);
+        assert_eq!(layers[0].bounds.unwrap().max_x, 30.0);
+        assert_eq!(layers[1].cut_length, 30.0);
+
+        let mut handler = GCodeValueHandlerStatistics::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        assert_eq!(handler.statistics.layers.len(), 5);
+    }
+}
@@ -0,0 +1,497 @@
+use crate::command::{GCodeCommand, GCodePlane};
+use crate::handler::{GCodeFlow, GCodeValueHandler, GCodeValueHandlerPathLayer};
+use crate::modal::{ARC_FLATTEN_TOLERANCE, GCodeModalState, GCodeMotion, GCodeSpline};
+use crate::parser::{GCodeParser, GCodeValue};
+use crate::path_bounds;
+use crate::stats::GCodeBounds;
+use crate::writer::{SvgPathWriter, format_number};
+use std::fmt::{Display, Formatter};
+
+///
+/// @author <a href="mailto: angcyo@126.com">angcyo</a>
+/// @date 2026/10/18
+///
+/// svg文档, 单位mm
+/// - 每一组路径使用一个`<g>`, 有自己的颜色
+/// - `viewBox`使用所有路径的范围, 加上边距
+#[derive(Clone, Debug)]
+pub struct SvgDocument {
+    /// 每一组路径
+    pub groups: Vec<SvgGroup>,
+    /// 所有路径的范围
+    pub bounds: Option<GCodeBounds>,
+    /// 线宽, mm
+    pub stroke_width: f64,
+    /// 四周的边距, mm
+    pub margin: f64,
+    /// 是否翻转Y轴, GCode的Y轴向上, svg的Y轴向下
+    pub flip_y: bool,
+    /// 保留几位小数点
+    pub digit: usize,
+}
+
+/// svg中的一组路径, 对应一个`<g>`
+#[derive(Clone, Debug, Default)]
+pub struct SvgGroup {
+    /// `id`
+    pub id: String,
+    /// 线的颜色
+    pub stroke: String,
+    /// 是否使用虚线, 比如空走
+    pub dashed: bool,
+    /// 每一个`<path>`的`d`数据
+    pub paths: Vec<String>,
+}
+
+impl Default for SvgDocument {
+    fn default() -> Self {
+        SvgDocument {
+            groups: vec![],
+            bounds: None,
+            stroke_width: 0.1,
+            margin: 1.0,
+            flip_y: false,
+            digit: 3,
+        }
+    }
+}
+
+/// 图层的颜色
+pub const SVG_LAYER_COLORS: [&str; 8] = [
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#17becf",
+];
+
+/// 空走的颜色
+pub const SVG_TRAVEL_COLOR: &str = "#a0a0a0";
+
+impl SvgDocument {
+    /// 添加[Path], 曲线不展平
+    /// - [id] 组的`id`, 相同时添加到同一组
+    /// - [stroke] 线的颜色, 只在新建组时使用
+    pub fn add_path(&mut self, id: &str, stroke: &str, path: &lyon_path::Path) {
+        if path.iter().next().is_none() {
+            return;
+        }
+        let (min_x, min_y, max_x, max_y) = path_bounds(path);
+        GCodeBounds::add_point(&mut self.bounds, min_x as f64, min_y as f64);
+        GCodeBounds::add_point(&mut self.bounds, max_x as f64, max_y as f64);
+
+        let mut writer = SvgPathWriter::new(self.digit);
+        path.iter().for_each(|event| match event {
+            lyon_path::Event::Begin { at } => {
+                writer.move_to(at.x as f64, at.y as f64);
+            }
+            lyon_path::Event::Line { to, .. } => {
+                writer.line_to(to.x as f64, to.y as f64);
+            }
+            lyon_path::Event::Quadratic { ctrl, to, .. } => {
+                writer.bezier_to(ctrl.x as f64, ctrl.y as f64, to.x as f64, to.y as f64);
+            }
+            lyon_path::Event::Cubic {
+                ctrl1, ctrl2, to, ..
+            } => {
+                writer.bezier3_to(
+                    ctrl1.x as f64,
+                    ctrl1.y as f64,
+                    ctrl2.x as f64,
+                    ctrl2.y as f64,
+                    to.x as f64,
+                    to.y as f64,
+                );
+            }
+            lyon_path::Event::End { close, .. } => {
+                if close {
+                    writer.write_line("Z");
+                }
+            }
+        });
+        self.group(id, stroke).paths.push(writer.to_string());
+    }
+
+    /// 获取或者新建一组
+    pub fn group(&mut self, id: &str, stroke: &str) -> &mut SvgGroup {
+        let index = match self.groups.iter().position(|group| group.id == id) {
+            Some(index) => index,
+            None => {
+                self.groups.push(SvgGroup {
+                    id: id.to_string(),
+                    stroke: stroke.to_string(),
+                    ..Default::default()
+                });
+                self.groups.len() - 1
+            }
+        };
+        &mut self.groups[index]
+    }
+
+    fn format_value(&self, value: f64) -> String {
+        format_number(value, self.digit)
+    }
+}
+
+impl Display for SvgDocument {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let bounds = self.bounds.unwrap_or(GCodeBounds {
+            min_x: 0.0,
+            min_y: 0.0,
+            max_x: 0.0,
+            max_y: 0.0,
+        });
+        let width = bounds.width() + self.margin * 2.0;
+        let height = bounds.height() + self.margin * 2.0;
+        let x = bounds.min_x - self.margin;
+        //翻转之后, 最上面是最大的Y
+        let y = if self.flip_y {
+            -bounds.max_y - self.margin
+        } else {
+            bounds.min_y - self.margin
+        };
+        writeln!(f, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            f,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}mm" height="{h}mm" viewBox="{} {} {w} {h}">"#,
+            self.format_value(x),
+            self.format_value(y),
+            w = self.format_value(width),
+            h = self.format_value(height),
+        )?;
+        write!(
+            f,
+            r#"<g fill="none" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round""#,
+            self.format_value(self.stroke_width)
+        )?;
+        if self.flip_y {
+            write!(f, r#" transform="scale(1 -1)""#)?;
+        }
+        writeln!(f, ">")?;
+        for group in &self.groups {
+            write!(
+                f,
+                r#"<g id="{}" stroke="{}""#,
+                escape_xml(&group.id),
+                escape_xml(&group.stroke)
+            )?;
+            if group.dashed {
+                let dash = self.format_value(self.stroke_width * 4.0);
+                write!(f, r#" stroke-dasharray="{} {}""#, dash, dash)?;
+            }
+            writeln!(f, ">")?;
+            for path in &group.paths {
+                writeln!(f, r#"<path d="{}"/>"#, path)?;
+            }
+            writeln!(f, "</g>")?;
+        }
+        writeln!(f, "</g>")?;
+        write!(f, "</svg>")
+    }
+}
+
+/// 转义xml属性中的特殊字符
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 将解析出来的每一层转换成svg文档, 每一层一组
+/// - [show_travel] 是否显示空走, 所有的空走在最后一组
+pub fn layers_to_svg(layers: &[GCodeValueHandlerPathLayer], show_travel: bool) -> SvgDocument {
+    let mut document = SvgDocument {
+        flip_y: true,
+        ..Default::default()
+    };
+    for (index, layer) in layers.iter().enumerate() {
+        let color = SVG_LAYER_COLORS[index % SVG_LAYER_COLORS.len()];
+        document.add_path(&format!("layer-{}", index), color, &layer.path);
+    }
+    if show_travel {
+        for layer in layers {
+            document.add_path("travel", SVG_TRAVEL_COLOR, &layer.travel_path);
+        }
+        if let Some(group) = document
+            .groups
+            .iter_mut()
+            .find(|group| group.id == "travel")
+        {
+            group.dashed = true;
+        }
+    }
+    document
+}
+
+/// svg的分组方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SvgGroupBy {
+    /// 每一层一组, 只有Z的移动会开始新的一层
+    #[default]
+    Layer,
+    /// 每一种功率`S`一组, 颜色从低功率的蓝色到高功率的红色
+    Power,
+}
+
+/// 一组路径的画笔, 只有和上一段不连续时才会`M`
+struct SvgPen {
+    /// 分组的键, 层的序号或者功率
+    key: f64,
+    writer: SvgPathWriter,
+    /// 画笔的位置
+    last: Option<(f64, f64)>,
+}
+
+impl SvgPen {
+    fn new(key: f64, digit: usize) -> Self {
+        SvgPen {
+            key,
+            writer: SvgPathWriter::new(digit),
+            last: None,
+        }
+    }
+
+    /// 绘制一段运动, 圆弧和样条不展平
+    fn motion(&mut self, motion: &GCodeMotion) {
+        let from = (motion.from.x, motion.from.y);
+        let (x, y) = (motion.to.x, motion.to.y);
+        if self.last != Some(from) {
+            self.writer.move_to(from.0, from.1);
+        }
+        match (&motion.arc, &motion.spline) {
+            (Some(arc), _) if arc.plane != GCodePlane::XY => {
+                //其他平面的圆弧投影到XY平面之后不是圆弧
+                for to in motion.flatten(ARC_FLATTEN_TOLERANCE) {
+                    self.writer.line_to(to.x, to.y);
+                }
+            }
+            (Some(arc), _) => {
+                self.writer
+                    .arc_to(x, y, arc.cx, arc.cy, arc.sweep_angle < 0.0);
+            }
+            (None, Some(GCodeSpline::Cubic { ctrl1, ctrl2 })) => {
+                self.writer
+                    .bezier3_to(ctrl1.0, ctrl1.1, ctrl2.0, ctrl2.1, x, y);
+            }
+            (None, Some(GCodeSpline::Quadratic { ctrl })) => {
+                self.writer.bezier_to(ctrl.0, ctrl.1, x, y);
+            }
+            (None, None) => self.writer.line_to(x, y),
+        }
+        self.last = Some((x, y));
+    }
+}
+
+/// 将GCode转换成svg文档, 圆弧和样条不展平
+/// - 切割/空走的判断和[crate::handler::GCodeValueHandlerPath]相同
+/// - 结果使用[GCodeValueHandlerSvg::document]获取
+pub struct GCodeValueHandlerSvg {
+    /// 模态状态, 在行与行之间保持
+    pub modal: GCodeModalState,
+    /// 激光模式, 参考[GCodeModalState::is_cut]
+    pub laser_mode: bool,
+    /// 激光模式下`G0`是否总是关闭激光, 参考[GCodeModalState::is_cut]
+    pub rapid_laser_off: bool,
+    /// 分组方式
+    pub group_by: SvgGroupBy,
+    /// 是否显示空走
+    pub show_travel: bool,
+    /// 保留几位小数点
+    pub digit: usize,
+    //--
+    /// 每一组切割的路径
+    pens: Vec<SvgPen>,
+    /// 空走的路径
+    travel: SvgPen,
+    /// 切割的范围
+    bounds: Option<GCodeBounds>,
+    /// 当前层的序号
+    layer: usize,
+}
+
+impl Default for GCodeValueHandlerSvg {
+    fn default() -> Self {
+        GCodeValueHandlerSvg {
+            modal: GCodeModalState::default(),
+            laser_mode: false,
+            rapid_laser_off: true,
+            group_by: SvgGroupBy::Layer,
+            show_travel: false,
+            digit: 3,
+            pens: vec![],
+            travel: SvgPen::new(0.0, 3),
+            bounds: None,
+            layer: 0,
+        }
+    }
+}
+
+impl GCodeValueHandlerSvg {
+    /// 处理一段运动
+    fn handle_motion(&mut self, motion: &GCodeMotion) {
+        if !motion.have_xy() {
+            if motion.params.z.is_some() && motion.cycle.is_none() {
+                //只有Z的移动, 新的一层
+                self.layer += 1;
+            }
+            return;
+        }
+        let is_cut = self
+            .modal
+            .is_cut(motion.mode, self.laser_mode, self.rapid_laser_off);
+        if !is_cut {
+            if self.show_travel {
+                GCodeBounds::add_motion(&mut self.bounds, motion);
+                self.travel.motion(motion);
+            }
+            return;
+        }
+        GCodeBounds::add_motion(&mut self.bounds, motion);
+        let key = match self.group_by {
+            SvgGroupBy::Layer => self.layer as f64,
+            SvgGroupBy::Power => self.modal.spindle_speed,
+        };
+        let index = match self.pens.iter().position(|pen| pen.key == key) {
+            Some(index) => index,
+            None => {
+                self.pens.push(SvgPen::new(key, self.digit));
+                self.pens.len() - 1
+            }
+        };
+        self.pens[index].motion(motion);
+    }
+
+    /// 转换成svg文档
+    pub fn document(&self) -> SvgDocument {
+        let mut document = SvgDocument {
+            bounds: self.bounds,
+            flip_y: true,
+            digit: self.digit,
+            ..Default::default()
+        };
+        let mut pens = self.pens.iter().collect::<Vec<&SvgPen>>();
+        let max_power = pens.iter().map(|pen| pen.key).fold(0.0, f64::max);
+        if self.group_by == SvgGroupBy::Power {
+            pens.sort_by(|a, b| a.key.total_cmp(&b.key));
+        }
+        for pen in pens {
+            let (id, stroke) = match self.group_by {
+                SvgGroupBy::Layer => (
+                    format!("layer-{}", pen.key),
+                    SVG_LAYER_COLORS[pen.key as usize % SVG_LAYER_COLORS.len()].to_string(),
+                ),
+                SvgGroupBy::Power => {
+                    let ratio = if max_power > 0.0 {
+                        pen.key / max_power
+                    } else {
+                        0.0
+                    };
+                    (
+                        format!("power-{}", format_number(pen.key, self.digit)),
+                        format!(
+                            "hsl({}, 100%, 40%)",
+                            format_number(240.0 * (1.0 - ratio), 0)
+                        ),
+                    )
+                }
+            };
+            document
+                .group(&id, &stroke)
+                .paths
+                .push(pen.writer.to_string());
+        }
+        if self.travel.last.is_some() {
+            let group = document.group("travel", SVG_TRAVEL_COLOR);
+            group.dashed = true;
+            group.paths.push(self.travel.writer.to_string());
+        }
+        document
+    }
+}
+
+impl GCodeValueHandler for GCodeValueHandlerSvg {
+    fn start(&mut self) {
+        self.pens.clear();
+        self.travel = SvgPen::new(0.0, self.digit);
+        self.bounds = None;
+        self.layer = 0;
+    }
+
+    fn handle_gcode_value(&mut self, gcode_value_line: Vec<GCodeValue>) -> GCodeFlow {
+        let commands = GCodeCommand::from_values(&gcode_value_line);
+        for commands in self.modal.expand_cycle(&commands) {
+            if let Some(motion) = self.modal.apply(&commands) {
+                self.handle_motion(&motion);
+            }
+        }
+        GCodeFlow::Continue
+    }
+}
+
+/// 将GCode转换成svg文档字符串, 参考[GCodeValueHandlerSvg]
+/// - [group_by] 分组方式
+/// - [show_travel] 是否显示空走
+pub fn gcode_to_svg(gcode: &String, group_by: SvgGroupBy, show_travel: bool) -> String {
+    let mut handler = GCodeValueHandlerSvg {
+        group_by,
+        show_travel,
+        ..Default::default()
+    };
+    GCodeParser::new(gcode).parse(&mut handler);
+    handler.document().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::GCodeValueHandlerPath;
+    use crate::parser::GCodeParser;
+    use crate::svg::{SvgGroupBy, gcode_to_svg, layers_to_svg};
+    use crate::writer::SvgPathWriter;
+
+    #[test]
+    fn test_svg_path_arc() {
+        let mut writer = SvgPathWriter::new(3);
+        writer.move_to(10.0, 0.0);
+        //逆时针3/4圆
+        writer.arc_to(0.0, -10.0, 0.0, 0.0, false);
+        //顺时针半圆
+        writer.arc_to(0.0, 10.0, 0.0, 0.0, true);
+        //整圆
+        writer.arc_to(0.0, 10.0, 0.0, 5.0, false);
+        assert_eq!(
+            writer.to_string(),
+            "M10,0 A10,10 0 1 1 0,-10 A10,10 0 0 0 0,10 A5,5 0 0 1 0,0 A5,5 0 0 1 0,10"
+        );
+
+        //不保留小数时, 整数末尾的0不能去掉
+        let mut writer = SvgPathWriter::new(0);
+        writer.move_to(10.0, 0.0);
+        writer.line_to(20.4, -0.2);
+        assert_eq!(writer.to_string(), "M10,0 L20,0");
+    }
+
+    #[test]
+    fn test_gcode_to_svg() {
+        let gcode =
+            "G0 X0 Y0\nM3 S1000\nG1 X10 F1000\nG3 X0 Y0 I-5 J0\nG0 Z1\nG0 X20 Y20\nG1 X30 S500\nM5"
+                .to_string();
+        let svg = gcode_to_svg(&gcode, SvgGroupBy::Layer, true);
+        assert!(svg.starts_with("<?xml"));
+        assert!(svg.contains(r#"width="32mm" height="22mm" viewBox="-1 -21 32 22""#));
+        assert!(svg.contains(r#"transform="scale(1 -1)""#));
+        assert!(svg.contains(r##"<g id="layer-0" stroke="#1f77b4">"##));
+        assert!(svg.contains(r#"<path d="M0,0 L10,0 A5,5 0 0 1 0,0"/>"#));
+        assert!(svg.contains(r#"<path d="M20,20 L30,20"/>"#));
+        assert!(svg.contains(r##"<g id="travel" stroke="#a0a0a0" stroke-dasharray="0.4 0.4">"##));
+        assert!(svg.ends_with("</svg>"));
+
+        let svg = gcode_to_svg(&gcode, SvgGroupBy::Power, false);
+        assert!(svg.contains(r#"<g id="power-500" stroke="hsl(120, 100%, 40%)">"#));
+        assert!(svg.contains(r#"<g id="power-1000" stroke="hsl(0, 100%, 40%)">"#));
+        assert!(!svg.contains("travel"));
+
+        //解析出来的层
+        let mut handler = GCodeValueHandlerPath::default();
+        GCodeParser::new(&gcode).parse(&mut handler);
+        let document = layers_to_svg(&handler.layers, true);
+        assert_eq!(document.groups.len(), 3);
+        assert!(document.groups[2].dashed);
+    }
+}
@@ -1,12 +1,21 @@
+use crate::arc_fit::{GCodeArcFitter, GCodeFitSegment};
+use crate::checksum::{gcode_checksum_line, gcode_line_number_reset, gcode_split_comments};
+use crate::command::{GCodeCycle, GCodeParams};
+use crate::dialect::GCodeDialect;
+use std::f64::consts::{PI, TAU};
+use std::io::{self, BufWriter, Write};
+
 ///
 /// @author <a href="mailto: angcyo@126.com">angcyo</a>
 /// @date 2025/09/03
 ///
 /// 用来生成GCode字符串数据
+/// - 默认写入到内存中, 使用[GCodeWriter::to_string]获取
+/// - 使用[GCodeWriter::from_writer]直接写入到[Write], 比如文件, 不在内存中保存
 ///
-pub struct GCodeWriter {
+pub struct GCodeWriter<O: GCodeOutput = Vec<String>> {
     /// 写入的一行一行数据
-    lines: Vec<String>,
+    output: O,
 
     /// 保留几位小数点
     digit: usize,
@@ -16,6 +25,40 @@ pub struct GCodeWriter {
 
     /// 当前的Y坐标
     y: f64,
+
+    /// 下一行的行号, 有值时每一行都会加上行号和校验和
+    line_number: Option<usize>,
+
+    /// 当前的固定循环和参数, 之后的孔只输出坐标
+    cycle: Option<(GCodeCycle, GCodeParams)>,
+
+    /// 紧凑模式, 省略重复的运动指令和没有变化的坐标, `F`/`S`只在变化时输出
+    /// - 使用[write_line]写入的数据不会更新模态
+    pub compact: bool,
+
+    /// 之后运动的进给速度`F`
+    feed_rate: Option<f64>,
+
+    /// 之后运动的激光功率/主轴转速`S`
+    power: Option<f64>,
+
+    /// 上一行的运动指令
+    last_motion: Option<&'static str>,
+
+    /// 上一次输出的`F`
+    last_feed_rate: Option<f64>,
+
+    /// 上一次输出的`S`
+    last_power: Option<f64>,
+
+    /// 是否输出过坐标, 之前的位置未知
+    has_position: bool,
+
+    /// 激光是否打开, 使用[laser_on]/[laser_off]更新
+    is_laser_on: bool,
+
+    /// 固件方言, 控制激光的开关/功率范围/圆弧/开头和结尾的指令
+    pub dialect: GCodeDialect,
 }
 
 /// 实现Default
@@ -27,16 +70,82 @@ impl Default for GCodeWriter {
 
 impl GCodeWriter {
     pub fn new(digit: usize) -> Self {
+        Self::with_output(vec![], digit)
+    }
+
+    /// 使用指定的固件方言
+    pub fn with_dialect(digit: usize, dialect: GCodeDialect) -> Self {
+        Self {
+            dialect,
+            ..Self::new(digit)
+        }
+    }
+
+    pub fn to_string(&self) -> String {
+        self.output.join("\n")
+    }
+}
+
+impl<W: Write> GCodeWriter<GCodeIoOutput<W>> {
+    /// 写入到[Write], 每一行之间使用`\n`分隔, 和[GCodeWriter::to_string]的内容相同
+    pub fn from_writer(writer: W, digit: usize) -> Self {
+        Self::with_output(GCodeIoOutput::new(writer, "\n"), digit)
+    }
+
+    /// 刷新缓冲区, 返回写入过程中的第一个错误
+    pub fn finish(self) -> io::Result<W> {
+        self.output.finish()
+    }
+}
+
+impl<O: GCodeOutput> GCodeWriter<O> {
+    /// 写入到指定的输出
+    pub fn with_output(output: O, digit: usize) -> Self {
         Self {
-            lines: vec![],
+            output,
             digit,
             x: 0.0,
             y: 0.0,
+            line_number: None,
+            cycle: None,
+            compact: false,
+            feed_rate: None,
+            power: None,
+            last_motion: None,
+            last_feed_rate: None,
+            last_power: None,
+            has_position: false,
+            is_laser_on: false,
+            dialect: GCodeDialect::default(),
         }
     }
 
+    /// 写入一行数据
+    /// - 开启了行号时, 每一行都会加上行号和校验和
+    /// - 空行和只有注释的行没有指令, 原样输出, 不占用行号
     pub fn write_line(&mut self, line: &str) {
-        self.lines.push(line.to_string());
+        if let Some(line_number) = self.line_number.as_mut() {
+            for line in line.split('\n') {
+                let line = line.strip_suffix('\r').unwrap_or(line);
+                if gcode_split_comments(line).0.is_empty() {
+                    self.output.push_line(line);
+                } else {
+                    self.output
+                        .push_line(&gcode_checksum_line(*line_number, line));
+                    *line_number += 1;
+                }
+            }
+        } else {
+            self.output.push_line(line);
+        }
+    }
+
+    /// 开启行号和校验和, 并输出`M110`重置行号
+    /// - Marlin/RepRap 串口协议使用, `N123 G1 X1*57`
+    /// - [line_number] `M110`行的行号, 之后的行号从`line_number + 1`开始
+    pub fn reset_line_number(&mut self, line_number: usize) {
+        self.output.push_line(&gcode_line_number_reset(line_number));
+        self.line_number = Some(line_number + 1);
     }
 
     pub fn write_lines(&mut self, lines: &[&str]) {
@@ -45,64 +154,371 @@ impl GCodeWriter {
         }
     }
 
-    pub fn to_string(&self) -> String {
-        self.lines.join("\n")
-    }
-
     //--
 
     fn format_value(&self, value: f64) -> String {
-        format!("{:.precision$}", value, precision = self.digit)
-            .trim_end_matches('0')
-            .trim_end_matches('.')
-            .to_string()
+        format_number(value, self.digit)
     }
 
-    pub fn move_to(&mut self, x: f64, y: f64) {
+    /// 设置之后运动的进给速度`F`, [None]不输出
+    /// - 紧凑模式下只在变化时输出
+    pub fn set_feed_rate(&mut self, feed_rate: Option<f64>) {
+        self.feed_rate = feed_rate;
+    }
+
+    /// 设置之后运动的激光功率/主轴转速`S`, [None]不输出
+    /// - 紧凑模式下只在变化时输出
+    pub fn set_power(&mut self, power: Option<f64>) {
+        self.power = power;
+    }
+
+    /// 输出方言开头的指令
+    pub fn begin(&mut self) {
+        for line in self.dialect.header.clone() {
+            self.write_line(&line);
+        }
+    }
+
+    /// 输出方言结尾的指令
+    /// - 激光已经关闭时, 跳过结尾中关闭激光的指令
+    pub fn end(&mut self) {
+        self.end_cycle();
+        let laser_off = self.dialect.laser_off();
+        for line in self.dialect.footer.clone() {
+            if !self.is_laser_on && laser_off == Some(line.as_str()) {
+                continue;
+            }
+            self.write_line(&line);
+        }
+        self.is_laser_on = false;
+    }
+
+    /// 按照方言打开激光
+    /// - [power] 功率`0~1`, 会缩放到方言的功率范围
+    pub fn laser_on(&mut self, power: f64) {
+        if let Some(line) = self.dialect.laser_on(power) {
+            self.write_line(&line);
+        }
+        self.is_laser_on = true;
+        if self.dialect.power_on_moves {
+            self.power = Some(self.dialect.scale_power(power));
+        }
+    }
+
+    /// 按照方言关闭激光
+    pub fn laser_off(&mut self) {
+        if let Some(line) = self.dialect.laser_off() {
+            self.write_line(line);
+        }
+        self.is_laser_on = false;
+        if self.dialect.power_on_moves {
+            self.power = None;
+        }
+    }
+
+    /// 输出一行运动
+    /// - [code] 运动指令, 紧凑模式下和上一行相同时不输出
+    /// - [always_xy] 是否总是输出`X`/`Y`, 否则紧凑模式下没有变化的坐标不输出
+    /// - [params] 其他参数, 比如圆弧的`I`/`J`
+    fn write_motion(
+        &mut self,
+        code: &'static str,
+        x: f64,
+        y: f64,
+        always_xy: bool,
+        params: &[(char, f64)],
+    ) {
+        self.end_cycle();
+        let compact = self.compact;
+        let mut words = vec![];
+        let mut axis = |letter: char, value: f64, current: f64| {
+            let value = format_number(value, self.digit);
+            if !compact
+                || always_xy
+                || !self.has_position
+                || value != format_number(current, self.digit)
+            {
+                words.push(format!("{}{}", letter, value));
+            }
+        };
+        axis('X', x, self.x);
+        axis('Y', y, self.y);
+        for (letter, value) in params {
+            words.push(format!("{}{}", letter, self.format_value(*value)));
+        }
+        let mut modal = |letter: char, value: Option<f64>, last: &mut Option<f64>| {
+            if let Some(value) = value {
+                if !compact || *last != Some(value) {
+                    words.push(format!("{}{}", letter, format_number(value, self.digit)));
+                }
+                *last = Some(value);
+            }
+        };
+        modal('F', self.feed_rate, &mut self.last_feed_rate);
+        modal('S', self.power, &mut self.last_power);
         self.x = x;
         self.y = y;
-        self.write_line(&format!(
-            "G0 X{} Y{}",
-            self.format_value(x),
-            self.format_value(y),
-        ));
+        self.has_position = true;
+        if compact && words.is_empty() {
+            //没有任何变化
+            return;
+        }
+        if !compact || self.last_motion != Some(code) {
+            words.insert(0, code.to_string());
+        }
+        self.last_motion = Some(code);
+        self.write_line(&words.join(" "));
     }
 
-    pub fn line_to(&mut self, x: f64, y: f64) {
+    /// 设置当前位置, 不输出
+    /// - 比如原样输出了一行运动之后, 同步当前位置
+    pub fn set_position(&mut self, x: f64, y: f64) {
         self.x = x;
         self.y = y;
-        self.write_line(&format!(
-            "G1 X{} Y{}",
-            self.format_value(x),
-            self.format_value(y),
-        ));
+        self.has_position = true;
+    }
+
+    /// 快速移动`G0`
+    pub fn move_to(&mut self, x: f64, y: f64) {
+        self.write_motion("G0", x, y, false, &[]);
+    }
+
+    /// 直线插补`G1`
+    pub fn line_to(&mut self, x: f64, y: f64) {
+        self.write_motion("G1", x, y, false, &[]);
+    }
+
+    /// 直线插补`G1`, 同时移动Z轴, 比如斜线下刀
+    pub fn line_to_z(&mut self, x: f64, y: f64, z: f64) {
+        self.write_motion("G1", x, y, false, &[('Z', z)]);
+    }
+
+    /// 只移动Z轴, 比如抬刀/下刀
+    /// - [rapid] 是否使用`G0`, 否则使用`G1`和当前的进给速度`F`
+    pub fn z_to(&mut self, z: f64, rapid: bool) {
+        self.end_cycle();
+        let code = if rapid { "G0" } else { "G1" };
+        let mut line = format!("{} Z{}", code, self.format_value(z));
+        if let Some(feed_rate) = self.feed_rate.filter(|_| !rapid) {
+            if !self.compact || self.last_feed_rate != Some(feed_rate) {
+                line.push_str(&format!(" F{}", self.format_value(feed_rate)));
+            }
+            self.last_feed_rate = Some(feed_rate);
+        }
+        self.last_motion = Some(code);
+        self.write_line(&line);
     }
 
     /// 顺时针绘制一个圆弧
     /// - `G2` 顺时针画弧
     /// - `G3` 逆时针画弧
     /// - [clockwise] 是否顺时针绘制
+    /// - 方言不支持圆弧时, 按照[GCodeDialect::arc_tolerance]展平成`G1`
     pub fn arc_to(&mut self, x: f64, y: f64, cx: f64, cy: f64, clockwise: bool) {
+        if !self.dialect.arc_support {
+            self.flatten_arc_to(x, y, cx, cy, clockwise);
+            return;
+        }
+        let i = cx - self.x;
+        let j = cy - self.y;
+        let code = if clockwise { "G2" } else { "G3" };
+        self.write_motion(code, x, y, true, &[('I', i), ('J', j)]);
+    }
+
+    /// 圆弧展平成直线, 起点和终点相同时为整圆
+    fn flatten_arc_to(&mut self, x: f64, y: f64, cx: f64, cy: f64, clockwise: bool) {
+        let radius = (self.x - cx).hypot(self.y - cy);
+        let start_angle = (self.y - cy).atan2(self.x - cx);
+        let end_angle = (y - cy).atan2(x - cx);
+        let mut sweep = if clockwise {
+            (start_angle - end_angle).rem_euclid(TAU)
+        } else {
+            (end_angle - start_angle).rem_euclid(TAU)
+        };
+        if sweep <= f64::EPSILON {
+            sweep = TAU;
+        }
+        //每一段的弦高不超过公差
+        let tolerance = self.dialect.arc_tolerance.min(radius);
+        let step = 2.0 * (1.0 - tolerance / radius).acos();
+        let count = if step > 0.0 {
+            (sweep / step).ceil().max(1.0) as usize
+        } else {
+            1
+        };
+        let direction = if clockwise { -1.0 } else { 1.0 };
+        for i in 1..count {
+            let angle = start_angle + direction * sweep * i as f64 / count as f64;
+            self.line_to(cx + radius * angle.cos(), cy + radius * angle.sin());
+        }
+        self.line_to(x, y);
+    }
+
+    /// 从当前位置连接折线, 在同一个圆弧上的点使用`G2`/`G3`, 其他的点使用`G1`
+    /// - [points] 折线的点, 不包含当前位置
+    /// - [fitter] 圆弧拟合参数
+    /// - 方言不支持圆弧时, 全部使用`G1`
+    pub fn polyline_to(&mut self, points: &[(f64, f64)], fitter: &GCodeArcFitter) {
+        if !self.dialect.arc_support {
+            for (x, y) in points {
+                self.line_to(*x, *y);
+            }
+            return;
+        }
+        let mut polyline = vec![(self.x, self.y)];
+        polyline.extend_from_slice(points);
+        for segment in fitter.fit(&polyline) {
+            match segment {
+                GCodeFitSegment::Line(index) => {
+                    self.line_to(polyline[index].0, polyline[index].1);
+                }
+                GCodeFitSegment::Arc {
+                    end,
+                    cx,
+                    cy,
+                    clockwise,
+                } => {
+                    self.arc_to(polyline[end].0, polyline[end].1, cx, cy, clockwise);
+                }
+            }
+        }
+    }
+
+    /// 三次贝塞尔曲线
+    /// - `G5` 三次B样条, `I`/`J`为第一个控制点相对于起点的偏移, `P`/`Q`为第二个控制点相对于终点的偏移
+    pub fn cubic_to(&mut self, c1x: f64, c1y: f64, c2x: f64, c2y: f64, x: f64, y: f64) {
+        let i = c1x - self.x;
+        let j = c1y - self.y;
+        let params = [('I', i), ('J', j), ('P', c2x - x), ('Q', c2y - y)];
+        self.write_motion("G5", x, y, true, &params);
+    }
+
+    /// 二次贝塞尔曲线
+    /// - `G5.1` 二次B样条, `I`/`J`为控制点相对于起点的偏移
+    pub fn quadratic_to(&mut self, cx: f64, cy: f64, x: f64, y: f64) {
         let i = cx - self.x;
         let j = cy - self.y;
+        self.write_motion("G5.1", x, y, true, &[('I', i), ('J', j)]);
+    }
+
+    /// 固定循环钻孔
+    /// - 和上一个孔的循环/参数相同时只输出坐标
+    /// - 之后的其他运动之前会输出`G80`取消循环
+    /// - [params] 循环的参数`Z`/`R`/`Q`/`P`/`F`等, `X`/`Y`会被忽略
+    pub fn drill_to(&mut self, cycle: GCodeCycle, x: f64, y: f64, params: &GCodeParams) {
         self.x = x;
         self.y = y;
-        self.write_line(&format!(
-            "{} X{} Y{} I{} J{}",
-            if clockwise { "G2" } else { "G3" },
-            self.format_value(x),
-            self.format_value(y),
-            self.format_value(i),
-            self.format_value(j),
-        ));
+        self.has_position = true;
+        let params = GCodeParams {
+            x: None,
+            y: None,
+            ..params.clone()
+        };
+        let hole = format!("X{} Y{}", self.format_value(x), self.format_value(y));
+        if self.cycle.as_ref() == Some(&(cycle, params.clone())) {
+            self.write_line(&hole);
+            return;
+        }
+        let params_code = params.to_gcode(self.digit);
+        if params_code.is_empty() {
+            self.write_line(&format!("{} {}", cycle.code(), hole));
+        } else {
+            self.write_line(&format!("{} {} {}", cycle.code(), hole, params_code));
+        }
+        self.cycle = Some((cycle, params));
+        self.last_motion = None;
+    }
+
+    /// 输出`G80`取消固定循环, 如果有
+    pub fn end_cycle(&mut self) {
+        if self.cycle.take().is_some() {
+            self.write_line("G80");
+        }
+    }
+}
+
+/// 格式化数值, 并去掉末尾多余的0
+/// - [digit] 保留几位小数点
+pub fn format_number(value: f64, digit: usize) -> String {
+    let value = format!("{:.precision$}", value, precision = digit);
+    let value = if value.contains('.') {
+        value.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        value.as_str()
+    };
+    //避免输出`-0`
+    if value == "-0" {
+        "0".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// 写入器的输出, 接收一行一行的数据
+pub trait GCodeOutput {
+    /// 写入一行数据, 不包含分隔符
+    fn push_line(&mut self, line: &str);
+}
+
+/// 保存在内存中
+impl GCodeOutput for Vec<String> {
+    fn push_line(&mut self, line: &str) {
+        self.push(line.to_string());
+    }
+}
+
+/// 带缓冲的写入到[Write], 比如文件/网络/压缩流
+/// - 写入失败之后不再写入, 错误在[GCodeIoOutput::finish]中返回
+pub struct GCodeIoOutput<W: Write> {
+    writer: BufWriter<W>,
+    /// 行与行之间的分隔符
+    separator: &'static str,
+    /// 是否写入过数据, 第一行之前不需要分隔符
+    has_line: bool,
+    /// 写入过程中的第一个错误
+    error: Option<io::Error>,
+}
+
+impl<W: Write> GCodeIoOutput<W> {
+    pub fn new(writer: W, separator: &'static str) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+            separator,
+            has_line: false,
+            error: None,
+        }
+    }
+
+    /// 刷新缓冲区, 返回写入过程中的第一个错误
+    pub fn finish(self) -> io::Result<W> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        self.writer.into_inner().map_err(|error| error.into_error())
+    }
+}
+
+impl<W: Write> GCodeOutput for GCodeIoOutput<W> {
+    fn push_line(&mut self, line: &str) {
+        if self.error.is_some() {
+            return;
+        }
+        let separator = if self.has_line { self.separator } else { "" };
+        self.has_line = true;
+        if let Err(error) = write!(self.writer, "{}{}", separator, line) {
+            self.error = Some(error);
+        }
     }
 }
 
 //--
 
-pub struct SvgPathWriter {
+/// 用来生成svg path数据
+/// - 默认写入到内存中, 使用[SvgPathWriter::to_string]获取
+/// - 使用[SvgPathWriter::from_writer]直接写入到[Write]
+pub struct SvgPathWriter<O: GCodeOutput = Vec<String>> {
     /// 写入的一行一行数据
-    lines: Vec<String>,
+    output: O,
 
     /// 保留几位小数点
     digit: usize,
@@ -122,8 +538,31 @@ impl Default for SvgPathWriter {
 
 impl SvgPathWriter {
     pub fn new(digit: usize) -> Self {
+        Self::with_output(vec![], digit)
+    }
+
+    pub fn to_string(&self) -> String {
+        self.output.join(" ")
+    }
+}
+
+impl<W: Write> SvgPathWriter<GCodeIoOutput<W>> {
+    /// 写入到[Write], 每一段之间使用空格分隔, 和[SvgPathWriter::to_string]的内容相同
+    pub fn from_writer(writer: W, digit: usize) -> Self {
+        Self::with_output(GCodeIoOutput::new(writer, " "), digit)
+    }
+
+    /// 刷新缓冲区, 返回写入过程中的第一个错误
+    pub fn finish(self) -> io::Result<W> {
+        self.output.finish()
+    }
+}
+
+impl<O: GCodeOutput> SvgPathWriter<O> {
+    /// 写入到指定的输出
+    pub fn with_output(output: O, digit: usize) -> Self {
         Self {
-            lines: vec![],
+            output,
             digit,
             x: 0.0,
             y: 0.0,
@@ -131,7 +570,7 @@ impl SvgPathWriter {
     }
 
     pub fn write_line(&mut self, line: &str) {
-        self.lines.push(line.to_string());
+        self.output.push_line(line);
     }
 
     pub fn write_lines(&mut self, lines: &[&str]) {
@@ -140,17 +579,10 @@ impl SvgPathWriter {
         }
     }
 
-    pub fn to_string(&self) -> String {
-        self.lines.join(" ")
-    }
-
     //--
 
     fn format_value(&self, value: f64) -> String {
-        format!("{:.precision$}", value, precision = self.digit)
-            .trim_end_matches('0')
-            .trim_end_matches('.')
-            .to_string()
+        format_number(value, self.digit)
     }
 
     pub fn move_to(&mut self, x: f64, y: f64) {
@@ -201,16 +633,40 @@ impl SvgPathWriter {
         ));
     }
 
-    /// 圆弧
+    /// 圆弧, 转换成svg的椭圆弧`A rx ry rotation large-arc sweep x y`
+    /// - [cx]/[cy] 圆心
+    /// - [clockwise] 是否顺时针, 角度减小的方向
+    /// - 起点和终点相同时为整圆, 拆分成两个半圆
     pub fn arc_to(&mut self, x: f64, y: f64, cx: f64, cy: f64, clockwise: bool) {
+        let radius = (self.x - cx).hypot(self.y - cy);
+        let start_angle = (self.y - cy).atan2(self.x - cx);
+        let end_angle = (y - cy).atan2(x - cx);
+        let sweep = if clockwise {
+            (start_angle - end_angle).rem_euclid(TAU)
+        } else {
+            (end_angle - start_angle).rem_euclid(TAU)
+        };
+        if sweep <= f64::EPSILON {
+            //整圆
+            let (mx, my) = (2.0 * cx - self.x, 2.0 * cy - self.y);
+            self.write_arc(radius, false, clockwise, mx, my);
+            self.write_arc(radius, false, clockwise, x, y);
+            return;
+        }
+        self.write_arc(radius, sweep > PI, clockwise, x, y);
+    }
+
+    fn write_arc(&mut self, radius: f64, large_arc: bool, clockwise: bool, x: f64, y: f64) {
         self.x = x;
         self.y = y;
+        let radius = self.format_value(radius);
         self.write_line(&format!(
-            "A{},{} {} {} {},{}",
-            self.format_value(cx),
-            self.format_value(cy),
-            if clockwise { 1 } else { 0 },
-            0,
+            "A{},{} 0 {} {} {},{}",
+            radius,
+            radius,
+            if large_arc { 1 } else { 0 },
+            //svg的正角度方向为1
+            if clockwise { 0 } else { 1 },
             self.format_value(x),
             self.format_value(y),
         ));
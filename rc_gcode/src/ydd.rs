@@ -108,8 +108,11 @@ pub fn gcode_to_ydd_bytes(
 
         //part2
         let mut item_part2_writer = ByteWriter::default();
-        item_part2_writer.write_int16(0, le); //激光功率
-        item_part2_writer.write_int32(60 * 1000, le); //雕刻速度mm/min
+        let power = layer.max_power();
+        let speed = layer.max_feed_rate();
+        let speed = if speed > 0.0 { speed } else { 60.0 * 1000.0 };
+        item_part2_writer.write_int16(power as i16, le); //激光功率
+        item_part2_writer.write_int32(speed as i32, le); //雕刻速度mm/min
         item_part2_writer.write_int8(1, le); //激光类型, 0:450激光 1:1064激光
         item_part2_writer.write_int16(60, le); //激光频率
         item_part2_writer.write_int16(20, le); //激光脉宽